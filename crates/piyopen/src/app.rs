@@ -6,7 +6,7 @@ use {
     crate::{add_fallback_font_to_egui, config::Config},
     eframe::egui::{self, mutex::Mutex},
     egui_file_dialog::FileDialog,
-    piyopiyo::{Event, N_KEYS, Track},
+    piyopiyo::{Event, N_KEYS, PianoKey, Song, TrackControls, TrackId},
     std::{panic::AssertUnwindSafe, path::Path, sync::Arc},
 };
 
@@ -20,14 +20,80 @@ struct SharedPiyoState {
 impl SharedPiyoState {
     pub fn new<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
         let data = std::fs::read(path)?;
+        let mut player = piyopiyo::Player::new(&data, SAMPLE_RATE)?;
+        player.clipping = piyopiyo::Clipping::Soft;
         Ok(SharedPiyoState {
-            player: piyopiyo::Player::new(&data, SAMPLE_RATE)?,
+            player,
             paused: true,
             volume: 1.0,
         })
     }
 }
 
+/// State of the player, copied out so the UI can be drawn without holding the lock
+///
+/// Changes made by the UI are written back with [`PlayerView::apply`].
+struct PlayerView {
+    /// The song being played, which is only cloned when edited
+    song: Arc<Song>,
+    n_events: usize,
+    event_cursor: u32,
+    /// Event cursor set by the UI
+    new_cursor: Option<u32>,
+    position_ms: u64,
+    paused: bool,
+    /// The track the view was taken for
+    track: TrackId,
+    /// Voice timers of `track`, including previews
+    timers: [f64; N_KEYS as usize],
+    /// Track controls, indexed by [`TrackId::index`]
+    controls: [TrackControls; 4],
+    /// Key to play on `track`
+    preview: Option<PianoKey>,
+}
+
+impl PlayerView {
+    fn new(shared: &SharedPiyoState, track: TrackId) -> Self {
+        let player = &shared.player;
+        Self {
+            song: player.song.clone(),
+            n_events: player.n_events(),
+            event_cursor: player.event_cursor,
+            new_cursor: None,
+            position_ms: player.position_ms(),
+            paused: shared.paused,
+            track,
+            timers: {
+                let preview = player.preview_voices(track).timers();
+                let mut timers = player.voices(track).timers();
+                for (t, p) in timers.iter_mut().zip(preview) {
+                    *t = t.max(p);
+                }
+                timers
+            },
+            controls: TrackId::ALL.map(|id| *player.track_controls(id)),
+            preview: None,
+        }
+    }
+    fn apply(self, shared: &mut SharedPiyoState) {
+        let player = &mut shared.player;
+        if !Arc::ptr_eq(&player.song, &self.song) {
+            player.song = self.song;
+        }
+        if let Some(cursor) = self.new_cursor {
+            player.event_cursor = cursor;
+        }
+        for (id, controls) in TrackId::ALL.into_iter().zip(self.controls) {
+            *player.track_controls_mut(id) = controls;
+        }
+        if let Some(key) = self.preview {
+            let mut down = [false; _];
+            down[usize::from(key)] = true;
+            player.preview(self.track, Event::from_keydown_array(down));
+        }
+    }
+}
+
 pub struct PiyopenApp {
     shared: Option<Arc<Mutex<SharedPiyoState>>>,
     file_dia: FileDialog,
@@ -50,22 +116,22 @@ fn spawn_playback_thread(shared: Arc<Mutex<SharedPiyoState>>) -> tinyaudio::Outp
         channel_sample_count: N_BUFFERED_SAMPLES,
     };
     tinyaudio::run_output_device(params, move |data| {
-        let mut buf: [i16; N_BUFFERED_SAMPLES * 2] = [0; _];
+        let mut buf: [f32; N_BUFFERED_SAMPLES * 2] = [0.0; _];
         let mut shared = shared.lock();
         let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
             if shared.paused {
                 for samp in buf.as_chunks_mut().0 {
-                    *samp = shared.player.next_sample();
+                    *samp = shared.player.next_sample_f32();
                 }
             } else {
-                shared.player.render_next(&mut buf);
+                shared.player.render_next_f32(&mut buf);
             }
         }));
         if let Err(e) = result {
             eprintln!("piyopiyo panic: {e:?}");
         }
-        for (f, i) in data.iter_mut().zip(&mut buf) {
-            *f = (*i as f32 / i16::MAX as f32) * shared.volume;
+        for (out, samp) in data.iter_mut().zip(&buf) {
+            *out = samp * shared.volume;
         }
     })
     .unwrap()
@@ -101,6 +167,57 @@ impl PiyopenApp {
             cfg,
         })
     }
+    /// Write the currently loaded song back to the file it was opened from
+    fn save(&mut self) {
+        if let Some(shared) = &self.shared
+            && let Some(path) = &self.open_path
+        {
+            let data = shared.lock().player.song.to_bytes();
+            if let Err(e) = std::fs::write(path, data) {
+                self.popup_msg = Some(e.to_string());
+            }
+        }
+    }
+    fn export_midi(&mut self, path: &Path) {
+        if let Some(shared) = &self.shared {
+            let song = shared.lock().player.song.clone();
+            let result = std::fs::File::create(path)
+                .and_then(|f| piyopiyo::midi::write(&song, std::io::BufWriter::new(f)));
+            if let Err(e) = result {
+                self.popup_msg = Some(e.to_string());
+            }
+        }
+    }
+    fn export_organya(&mut self, path: &Path) {
+        if let Some(shared) = &self.shared {
+            let song = shared.lock().player.song.clone();
+            let wave_bank = match &self.cfg.organya_wave_bank {
+                Some(bank_path) => match std::fs::read(bank_path) {
+                    Ok(data) => piyopiyo::organya::wave_bank_from_bytes(&data),
+                    Err(e) => {
+                        self.popup_msg = Some(format!("{bank_path}: {e}"));
+                        return;
+                    }
+                },
+                None => None,
+            };
+            let opts = piyopiyo::organya::WriteOptions {
+                wave_bank: wave_bank.as_deref(),
+                ..Default::default()
+            };
+            let result = std::fs::File::create(path)
+                .and_then(|f| piyopiyo::organya::write(&song, &opts, std::io::BufWriter::new(f)));
+            match result {
+                Ok(report) if !report.is_lossless() => {
+                    let losses: Vec<String> =
+                        report.losses.iter().map(ToString::to_string).collect();
+                    self.popup_msg = Some(losses.join("\n"));
+                }
+                Ok(_) => {}
+                Err(e) => self.popup_msg = Some(e.to_string()),
+            }
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Clone, Copy)]
@@ -109,19 +226,31 @@ enum TrackSelect {
     Percussion,
 }
 
+impl TrackSelect {
+    fn track_id(self) -> TrackId {
+        match self {
+            TrackSelect::Melody(idx) => TrackId::Melody(idx),
+            TrackSelect::Percussion => TrackId::Percussion,
+        }
+    }
+}
+
 enum FileDialogOp {
     OpenFile,
     AddFont,
+    ExportMidi,
+    ExportOrganya,
 }
 
 impl eframe::App for PiyopenApp {
     fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
         ctx.request_repaint();
-        let [ctrl, key_o, key_r, key_space] = ctx.input(|inp| {
+        let [ctrl, key_o, key_r, key_s, key_space] = ctx.input(|inp| {
             [
                 inp.modifiers.ctrl,
                 inp.key_pressed(egui::Key::O),
                 inp.key_pressed(egui::Key::R),
+                inp.key_pressed(egui::Key::S),
                 inp.key_pressed(egui::Key::Space),
             ]
         });
@@ -173,13 +302,10 @@ impl eframe::App for PiyopenApp {
         });
         if let Some(shared) = &mut self.shared {
             let event = Event::from_keydown_array(piano_keys);
-            let mut shared = shared.lock();
-            match self.track_select {
-                TrackSelect::Melody(idx) => {
-                    shared.player.song.melody_tracks[idx as usize].do_event(event)
-                }
-                TrackSelect::Percussion => shared.player.song.percussion_track.do_event(event),
-            }
+            shared
+                .lock()
+                .player
+                .preview(self.track_select.track_id(), event);
         }
         if ctrl && key_o {
             if let Some(path) = &self.open_path
@@ -200,6 +326,9 @@ impl eframe::App for PiyopenApp {
                 Err(e) => self.popup_msg = Some(e.to_string()),
             }
         }
+        if ctrl && key_s {
+            self.save();
+        }
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             top_panel::ui(self, ui, key_space);
         });
@@ -227,6 +356,8 @@ impl eframe::App for PiyopenApp {
                     Err(e) => self.popup_msg = Some(e.to_string()),
                 },
                 FileDialogOp::AddFont => add_fallback_font_to_egui(ctx, "fallback", &path).unwrap(),
+                FileDialogOp::ExportMidi => self.export_midi(&path),
+                FileDialogOp::ExportOrganya => self.export_organya(&path),
             }
         }
         if let Some(msg) = &self.popup_msg {
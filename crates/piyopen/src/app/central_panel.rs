@@ -1,97 +1,172 @@
 use {
     crate::{
-        app::{PiyopenApp, SharedPiyoState, TrackSelect},
+        app::{PiyopenApp, PlayerView, TrackSelect},
         draw_widgets::{envelope_widget, waveform_widget},
     },
     eframe::egui,
+    piyopiyo::Song,
+    std::sync::Arc,
 };
 
 pub fn ui(app: &mut PiyopenApp, ui: &mut egui::Ui) {
-    if let Some(shared) = app.shared.as_mut() {
-        let mut shared = shared.lock();
-        ui.style_mut().spacing.slider_width = ui.available_width() - 100.0;
-        let n_events = (shared.player.n_events() as u32).saturating_sub(1);
+    if let Some(shared) = app.shared.clone() {
+        // Only hold the lock while copying the state in and out, not while drawing
+        let mut view = PlayerView::new(&shared.lock(), app.track_select.track_id());
+        ui.style_mut().spacing.slider_width = ui.available_width() - 180.0;
+        let n_events = (view.n_events as u32).saturating_sub(1);
         ui.horizontal(|ui| {
-            ui.add(egui::Slider::new(
-                &mut shared.player.event_cursor,
-                0..=n_events,
-            ));
-            ui.label(format!("/{}", shared.player.n_events()));
+            let mut cursor = view.event_cursor;
+            if ui
+                .add(egui::Slider::new(&mut cursor, 0..=n_events))
+                .changed()
+            {
+                view.new_cursor = Some(cursor);
+            }
+            ui.label(format!("/{}", view.n_events));
+            let ms = view.position_ms;
+            ui.label(format!(
+                "{:02}:{:02}.{:03}",
+                ms / 60_000,
+                (ms / 1000) % 60,
+                ms % 1000
+            ))
+            .on_hover_text("Playback position");
         });
         ui.separator();
-        crate::app::piano_roll::ui(ui, app.track_select, &mut shared, n_events);
+        crate::app::piano_roll::ui(ui, app.track_select, &mut view, n_events);
         ui.separator();
         track_selector_ui(
             ui,
             &mut app.track_select,
-            &mut shared,
+            &mut view,
             &mut app.waveform_last_pos,
             &mut app.envelope_last_pos,
         );
+        view.apply(&mut shared.lock());
     }
 }
 
+/// Let `edit` change a copy of `value`, returning the copy if it changed
+///
+/// This way the song is only cloned (if shared) when it's actually edited.
+fn edited<T: Clone + PartialEq>(value: &T, edit: impl FnOnce(&mut T)) -> Option<T> {
+    let mut copy = value.clone();
+    edit(&mut copy);
+    (copy != *value).then_some(copy)
+}
+
 fn track_selector_ui(
     ui: &mut egui::Ui,
     track_select: &mut TrackSelect,
-    shared: &mut SharedPiyoState,
+    view: &mut PlayerView,
     waveform_last_pos: &mut Option<egui::Pos2>,
     envelope_last_pos: &mut Option<egui::Pos2>,
 ) {
+    let song = &mut view.song;
     ui.horizontal(|ui| {
         ui.vertical(|ui| {
-            ui.selectable_value(track_select, TrackSelect::Melody(0), "🎵 Track 1");
-            ui.selectable_value(track_select, TrackSelect::Melody(1), "🎵 Track 2");
-            ui.selectable_value(track_select, TrackSelect::Melody(2), "🎵 Track 3");
-            ui.selectable_value(track_select, TrackSelect::Percussion, "🔩 Track P");
+            for (sel, label) in [
+                (TrackSelect::Melody(0), "🎵 Track 1"),
+                (TrackSelect::Melody(1), "🎵 Track 2"),
+                (TrackSelect::Melody(2), "🎵 Track 3"),
+                (TrackSelect::Percussion, "🔩 Track P"),
+            ] {
+                ui.horizontal(|ui| {
+                    ui.selectable_value(track_select, sel, label);
+                    let controls = &mut view.controls[sel.track_id().index()];
+                    ui.toggle_value(&mut controls.mute, "M")
+                        .on_hover_text("Mute");
+                    ui.toggle_value(&mut controls.solo, "S")
+                        .on_hover_text("Solo");
+                });
+            }
+            ui.horizontal(|ui| {
+                ui.label("Gain")
+                    .on_hover_text("Playback gain of the selected track (not saved)");
+                let controls = &mut view.controls[track_select.track_id().index()];
+                ui.add(
+                    egui::DragValue::new(&mut controls.gain)
+                        .range(0.0..=4.0)
+                        .speed(0.01),
+                );
+            });
             ui.add_space(16.0);
             ui.horizontal(|ui| {
                 ui.label("Wait")
                     .on_hover_text("How much to wait before next event (in milliseconds)");
-                ui.add(egui::DragValue::new(&mut shared.player.song.event_wait_ms).range(1..=5000));
+                if let Some(wait) = edited(&song.event_wait_ms, |wait| {
+                    ui.add(egui::DragValue::new(wait).range(1..=5000));
+                }) {
+                    Arc::make_mut(song).event_wait_ms = wait;
+                }
             });
 
             ui.horizontal(|ui| {
                 ui.label("Repeat");
-                ui.add(egui::DragValue::new(
-                    &mut shared.player.song.repeat_range.start,
-                ));
-                ui.add(egui::DragValue::new(
-                    &mut shared.player.song.repeat_range.end,
-                ));
+                if let Some(range) = edited(&song.repeat_range, |range| {
+                    ui.add(egui::DragValue::new(&mut range.start));
+                    ui.add(egui::DragValue::new(&mut range.end));
+                }) {
+                    Arc::make_mut(song).repeat_range = range;
+                }
             });
         });
         match *track_select {
-            TrackSelect::Melody(idx) => {
-                let track = &mut shared.player.song.melody_tracks[usize::from(idx)];
-                waveform_widget(ui, &mut track.waveform, waveform_last_pos);
-                ui.vertical(|ui| {
-                    envelope_widget(ui, &mut track.envelope, envelope_last_pos);
-                    ui.label("Octave");
-                    ui.add(
-                        egui::DragValue::new(&mut track.octave)
-                            .range(0..=7)
-                            .speed(0.05),
-                    );
-                    ui.label("Length");
-                    ui.add(egui::DragValue::new(&mut track.len).speed(100.0));
-                    ui.label("Volume");
-                    ui.add(
-                        egui::DragValue::new(&mut track.base.vol)
-                            .range(0..=300)
-                            .speed(1.0),
-                    );
-                });
-            }
+            TrackSelect::Melody(idx) => melody_track_ui(
+                ui,
+                song,
+                usize::from(idx),
+                waveform_last_pos,
+                envelope_last_pos,
+            ),
             TrackSelect::Percussion => {
-                let track = &mut shared.player.song.percussion_track;
                 ui.label("Volume");
-                ui.add(
-                    egui::DragValue::new(&mut track.base.vol)
-                        .range(0..=300)
-                        .speed(1.0),
-                );
+                if let Some(vol) = edited(&song.percussion_track.base.vol, |vol| {
+                    ui.add(egui::DragValue::new(vol).range(0..=300).speed(1.0));
+                }) {
+                    Arc::make_mut(song).percussion_track.base.vol = vol;
+                }
             }
         };
     });
 }
+
+fn melody_track_ui(
+    ui: &mut egui::Ui,
+    song: &mut Arc<Song>,
+    idx: usize,
+    waveform_last_pos: &mut Option<egui::Pos2>,
+    envelope_last_pos: &mut Option<egui::Pos2>,
+) {
+    let track = &song.melody_tracks[idx];
+    if let Some(waveform) = edited(&track.waveform, |wave| {
+        waveform_widget(ui, wave, waveform_last_pos);
+    }) {
+        Arc::make_mut(song).melody_tracks[idx].waveform = waveform;
+    }
+    ui.vertical(|ui| {
+        let track = &song.melody_tracks[idx];
+        let envelope = edited(&track.envelope, |env| {
+            envelope_widget(ui, env, envelope_last_pos);
+        });
+        ui.label("Octave");
+        let octave = edited(&track.octave, |octave| {
+            ui.add(egui::DragValue::new(octave).range(0..=7).speed(0.05));
+        });
+        ui.label("Length");
+        let len = edited(&track.len, |len| {
+            ui.add(egui::DragValue::new(len).speed(100.0));
+        });
+        ui.label("Volume");
+        let vol = edited(&track.base.vol, |vol| {
+            ui.add(egui::DragValue::new(vol).range(0..=300).speed(1.0));
+        });
+        if envelope.is_some() || octave.is_some() || len.is_some() || vol.is_some() {
+            let track = &mut Arc::make_mut(song).melody_tracks[idx];
+            track.envelope = envelope.unwrap_or(track.envelope);
+            track.octave = octave.unwrap_or(track.octave);
+            track.len = len.unwrap_or(track.len);
+            track.base.vol = vol.unwrap_or(track.base.vol);
+        }
+    });
+}
@@ -1,47 +1,45 @@
 use {
-    crate::app::{SharedPiyoState, TrackSelect},
+    crate::app::{PlayerView, TrackSelect},
     eframe::egui,
-    piyopiyo::{Event, N_KEYS, PianoKey, Track, piano_keys},
+    piyopiyo::{Event, N_KEYS, PianoKey, Song, Track, Voices, piano_keys},
+    std::sync::Arc,
 };
 
-pub fn ui(
-    ui: &mut egui::Ui,
-    track_select: TrackSelect,
-    shared: &mut SharedPiyoState,
-    n_events: u32,
-) {
-    let track = track_sel_dyn(track_select, shared);
-    let timers = track.timers();
-    let max_time = track.note_duration(0);
+pub fn ui(ui: &mut egui::Ui, track_select: TrackSelect, view: &mut PlayerView, n_events: u32) {
+    // The editor plays the built-in drum kit, which default voices have
+    let max_time = track_sel_dyn(track_select, &view.song).note_duration(&Voices::default(), 0);
     let mut key_clicked = None;
     ui.horizontal(|ui| {
         ui.spacing_mut().item_spacing = egui::Vec2::ZERO;
-        key_clicked = piano_keys_ui(ui, &timers, max_time, egui::vec2(96.0, 16.0));
-        piano_hscroll_ui(ui, track_select, shared, n_events);
+        key_clicked = piano_keys_ui(ui, &view.timers, max_time, egui::vec2(96.0, 16.0));
+        piano_hscroll_ui(ui, track_select, view, n_events);
     });
     if let Some(key) = key_clicked {
-        play_key(track_select, shared, key);
+        view.preview = Some(key);
     }
 }
 
-fn play_key(track_select: TrackSelect, shared: &mut SharedPiyoState, key: u8) {
-    let mut down = [false; _];
-    down[key as usize] = true;
-    let track = track_sel_dyn(track_select, shared);
-    track.do_event(Event::from_keydown_array(down));
-}
-
-fn track_sel_dyn(track_select: TrackSelect, shared: &mut SharedPiyoState) -> &mut dyn Track {
+fn track_sel_dyn(track_select: TrackSelect, song: &Song) -> &dyn Track {
     match track_select {
-        TrackSelect::Melody(idx) => &mut shared.player.song.melody_tracks[idx as usize],
-        TrackSelect::Percussion => &mut shared.player.song.percussion_track,
+        TrackSelect::Melody(idx) => &song.melody_tracks[idx as usize],
+        TrackSelect::Percussion => &song.percussion_track,
     }
 }
 
+/// The event at `idx` of the selected track, cloning the song first if it's shared
+fn event_mut(track_select: TrackSelect, song: &mut Arc<Song>, idx: usize) -> &mut Event {
+    let song = Arc::make_mut(song);
+    let events = match track_select {
+        TrackSelect::Melody(n) => &mut song.melody_tracks[usize::from(n)].base.events,
+        TrackSelect::Percussion => &mut song.percussion_track.base.events,
+    };
+    &mut events[idx]
+}
+
 fn piano_hscroll_ui(
     ui: &mut egui::Ui,
     track_select: TrackSelect,
-    shared: &mut SharedPiyoState,
+    view: &mut PlayerView,
     n_events: u32,
 ) {
     let cur = ui.cursor();
@@ -58,17 +56,11 @@ fn piano_hscroll_ui(
         let mut x = rect.min.x + node_size;
         let y_off = rect.min.y;
         let p = ui.painter_at(clip);
-        let event_cursor = shared.player.event_cursor;
-        let paused = shared.paused;
-        let events = match track_select {
-            TrackSelect::Melody(n) => {
-                &mut shared.player.song.melody_tracks[usize::from(n)].base.events
-            }
-            TrackSelect::Percussion => &mut shared.player.song.percussion_track.base.events,
-        };
+        let event_cursor = view.event_cursor;
+        let paused = view.paused;
         let guide_color = ui.style().visuals.widgets.noninteractive.bg_stroke.color;
         let node_color = ui.style().visuals.widgets.hovered.weak_bg_fill;
-        for event in &mut *events {
+        for event in &track_sel_dyn(track_select, &view.song).base().events {
             let mut y = (y_off + ((N_KEYS - 1) as f32 * node_gapped)) + node_gapped / 2.0;
             for key in piano_keys() {
                 p.circle_filled(egui::pos2(x, y), 1.0, guide_color);
@@ -130,11 +122,13 @@ fn piano_hscroll_ui(
 
                 match action {
                     Action::Add => {
-                        events[event_off].set_key_down(key_idx);
-                        play_key(track_select, shared, key_idx);
+                        event_mut(track_select, &mut view.song, event_off).set_key_down(key_idx);
+                        view.preview = Some(key_idx);
+                    }
+                    Action::Del => {
+                        event_mut(track_select, &mut view.song, event_off).set_key_up(key_idx);
                     }
-                    Action::Del => events[event_off].set_key_up(key_idx),
-                    Action::SetPos => shared.player.event_cursor = event_off as u32,
+                    Action::SetPos => view.new_cursor = Some(event_off as u32),
                 }
             }
         }
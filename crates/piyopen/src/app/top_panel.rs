@@ -1,6 +1,7 @@
 use {
     crate::app::{FileDialogOp, PiyopenApp, SharedPiyoState},
     eframe::egui,
+    piyopiyo::Interpolation,
     std::path::Path,
 };
 
@@ -25,6 +26,17 @@ pub fn ui(app: &mut PiyopenApp, ui: &mut egui::Ui, space_key_down: bool) {
                     Err(e) => app.popup_msg = Some(e.to_string()),
                 }
             }
+            if app.shared.is_some() && ui.button("Save").clicked() {
+                app.save();
+            }
+            if app.shared.is_some() && ui.button("Export MIDI").clicked() {
+                app.file_dia.save_file();
+                app.file_dia.set_user_data(FileDialogOp::ExportMidi);
+            }
+            if app.shared.is_some() && ui.button("Export Organya").clicked() {
+                app.file_dia.save_file();
+                app.file_dia.set_user_data(FileDialogOp::ExportOrganya);
+            }
             if ui.button("🗛 Add fallback font").clicked() {
                 app.file_dia.pick_file();
                 app.file_dia.set_user_data(FileDialogOp::AddFont);
@@ -38,7 +50,7 @@ pub fn ui(app: &mut PiyopenApp, ui: &mut egui::Ui, space_key_down: bool) {
                 shared.paused ^= true;
             }
             if ui.button("⏮").on_hover_text("Seek to beginning").clicked() {
-                shared.player.event_cursor = 0;
+                shared.player.seek(0);
             }
             if ui
                 .button("⟲")
@@ -49,6 +61,35 @@ pub fn ui(app: &mut PiyopenApp, ui: &mut egui::Ui, space_key_down: bool) {
             }
             ui.label("🔉");
             ui.add(egui::Slider::new(&mut shared.volume, 0.0..=1.0));
+            let mut interp = shared.player.interpolation();
+            egui::ComboBox::from_id_salt("interp")
+                .selected_text(format!("{interp:?}"))
+                .show_ui(ui, |ui| {
+                    for mode in [
+                        Interpolation::Nearest,
+                        Interpolation::Linear,
+                        Interpolation::Cubic,
+                        Interpolation::BandLimited,
+                    ] {
+                        ui.selectable_value(&mut interp, mode, format!("{mode:?}"));
+                    }
+                })
+                .response
+                .on_hover_text("Waveform interpolation");
+            shared.player.set_interpolation(interp);
+            ui.add(
+                egui::DragValue::new(&mut shared.player.speed)
+                    .range(0.25..=4.0)
+                    .speed(0.01)
+                    .prefix("×"),
+            )
+            .on_hover_text("Playback speed (not saved)");
+            ui.add(
+                egui::DragValue::new(&mut shared.player.transpose)
+                    .range(-24..=24)
+                    .speed(0.1),
+            )
+            .on_hover_text("Transpose in semitones (not saved)");
         }
         ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
             if let Some(path) = &app.open_path {
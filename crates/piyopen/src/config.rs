@@ -9,6 +9,9 @@ pub struct Config {
     pub fallback_fonts: Vec<FallbackFont>,
     #[serde(default)]
     pub last_opened: Option<String>,
+    /// Organya's `WAVE100` waveforms, used to pick instruments when exporting to Organya
+    #[serde(default)]
+    pub organya_wave_bank: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
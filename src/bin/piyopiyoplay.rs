@@ -1,14 +1,175 @@
-use std::{io::Write, process::ExitCode};
+use {
+    piyopiyo::{Fade, FadeCurve, Interpolation, LoopMode, Player, Timing, TrackId},
+    std::{io::Write, process::ExitCode},
+};
+
+const USAGE: &str = "\
+Usage: piyopiyoplay [OPTIONS] <FILE>
+
+Options:
+  --mute <TRACK>         Silence TRACK
+  --solo <TRACK>         Only play soloed tracks
+  --gain <TRACK>=<GAIN>  Multiply the output of TRACK by GAIN
+  --loops <N>            Loop N times, then exit (default: loop forever)
+  --fade <MS>            Fade out over the last MS milliseconds (requires --loops)
+  --interp <MODE>        Waveform interpolation: nearest (default), linear, cubic or bandlimited
+  --exact-timing         Keep the song tempo exact instead of rounding like the original player
+  --speed <FACTOR>       Multiply the tempo by FACTOR
+  --transpose <N>        Shift melody tracks by N semitones
+  --fixed-point          Render voices with fixed point math
+
+TRACK is one of 1, 2, 3 (melody tracks) or p (percussion track).
+Options can be given multiple times.";
+
+struct Args {
+    path: String,
+    mute: Vec<TrackId>,
+    solo: Vec<TrackId>,
+    gain: Vec<(TrackId, f32)>,
+    loop_mode: LoopMode,
+    fade: Option<Fade>,
+    interpolation: Interpolation,
+    timing: Timing,
+    speed: f64,
+    transpose: i8,
+    fixed_point: bool,
+}
+
+fn parse_track(arg: &str) -> Result<TrackId, String> {
+    match arg {
+        "1" => Ok(TrackId::Melody(0)),
+        "2" => Ok(TrackId::Melody(1)),
+        "3" => Ok(TrackId::Melody(2)),
+        "p" | "P" => Ok(TrackId::Percussion),
+        _ => Err(format!("Invalid track: {arg}")),
+    }
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut path = None;
+    let mut mute = Vec::new();
+    let mut solo = Vec::new();
+    let mut gain = Vec::new();
+    let mut loop_mode = LoopMode::Forever;
+    let mut fade = None;
+    let mut interpolation = Interpolation::Nearest;
+    let mut timing = Timing::Truncated;
+    let mut speed = 1.0;
+    let mut transpose = 0;
+    let mut fixed_point = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--mute" => mute.push(parse_track(&value()?)?),
+            "--solo" => solo.push(parse_track(&value()?)?),
+            "--gain" => {
+                let value = value()?;
+                let (track, amount) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("Expected <TRACK>=<GAIN>, got {value}"))?;
+                let amount = amount
+                    .parse()
+                    .map_err(|e| format!("Invalid gain {amount}: {e}"))?;
+                gain.push((parse_track(track)?, amount));
+            }
+            "--loops" => {
+                let value = value()?;
+                let n = value
+                    .parse()
+                    .map_err(|e| format!("Invalid loop count {value}: {e}"))?;
+                loop_mode = LoopMode::Times(n);
+            }
+            "--fade" => {
+                let value = value()?;
+                let duration_ms = value
+                    .parse()
+                    .map_err(|e| format!("Invalid fade duration {value}: {e}"))?;
+                fade = Some(Fade {
+                    duration_ms,
+                    curve: FadeCurve::Linear,
+                });
+            }
+            "--interp" => {
+                interpolation = match value()?.as_str() {
+                    "nearest" => Interpolation::Nearest,
+                    "linear" => Interpolation::Linear,
+                    "cubic" => Interpolation::Cubic,
+                    "bandlimited" => Interpolation::BandLimited,
+                    other => return Err(format!("Invalid interpolation mode: {other}")),
+                };
+            }
+            "--exact-timing" => timing = Timing::Exact,
+            "--speed" => {
+                let value = value()?;
+                speed = value
+                    .parse()
+                    .map_err(|e| format!("Invalid speed {value}: {e}"))?;
+            }
+            "--transpose" => {
+                let value = value()?;
+                transpose = value
+                    .parse()
+                    .map_err(|e| format!("Invalid transpose {value}: {e}"))?;
+            }
+            "--fixed-point" => fixed_point = true,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {arg}")),
+            _ => path = Some(arg),
+        }
+    }
+    if fade.is_some() && loop_mode == LoopMode::Forever {
+        return Err("--fade requires --loops, as songs that loop forever never end".into());
+    }
+    Ok(Args {
+        path: path.ok_or("Need .pmd file")?,
+        mute,
+        solo,
+        gain,
+        loop_mode,
+        fade,
+        interpolation,
+        timing,
+        speed,
+        transpose,
+        fixed_point,
+    })
+}
 
 fn main() -> ExitCode {
-    let path = std::env::args().nth(1).expect("Need .pmd file");
-    let data = std::fs::read(&path).unwrap();
-    let mut player = piyopiyo::Player::new(&data, 44_100).unwrap();
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let path = &args.path;
+    let data = std::fs::read(path).unwrap();
+    let mut player = Player::new(&data, 44_100).unwrap();
+    player.loop_mode = args.loop_mode;
+    player.fade = args.fade;
+    player.set_interpolation(args.interpolation);
+    player.timing = args.timing;
+    player.speed = args.speed;
+    player.transpose = args.transpose;
+    player.set_fixed_point(args.fixed_point);
+    for track in args.mute {
+        player.track_controls_mut(track).mute = true;
+    }
+    for track in args.solo {
+        player.track_controls_mut(track).solo = true;
+    }
+    for (track, gain) in args.gain {
+        player.track_controls_mut(track).gain = gain;
+    }
     let mut buf = [0; 1024];
     let mut writer = std::io::stdout().lock();
     loop {
-        player.render_next(&mut buf);
-        let result = writer.write_all(bytemuck::cast_slice(&buf));
+        let written = player.render_next(&mut buf);
+        let result = writer.write_all(bytemuck::cast_slice(&buf[..written * 2]));
         eprint!(
             "Playing {path} {:04}/{:04}\r",
             player.event_cursor,
@@ -23,5 +184,9 @@ fn main() -> ExitCode {
                 }
             }
         }
+        if player.is_finished() {
+            eprintln!();
+            return ExitCode::SUCCESS;
+        }
     }
 }
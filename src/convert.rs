@@ -0,0 +1,67 @@
+//! Helpers shared by the importers of other music formats
+
+use {
+    crate::{Event, PianoKey},
+    alloc::vec::Vec,
+};
+
+/// Songs longer than this many events are rejected
+pub(crate) const MAX_EVENTS: u64 = 1 << 20;
+/// Voices are rendered at this rate, which is the unit of note lengths
+pub(crate) const VOICE_RATE: u64 = 22_050;
+/// MIDI note number of the lowest key of a melody track at octave 0 (C1)
+pub(crate) const OCTAVE_0_NOTE: u8 = 24;
+
+/// The octave of a melody track that can play the most of `notes` (MIDI note numbers)
+///
+/// Ties are resolved towards the lower octave.
+pub(crate) fn best_octave(notes: &[u8]) -> u8 {
+    (0..=7)
+        .max_by_key(|&oct| {
+            let lowest = OCTAVE_0_NOTE + 12 * oct;
+            let fitting = notes
+                .iter()
+                .filter(|note| (lowest..lowest + 24).contains(note))
+                .count();
+            (fitting, core::cmp::Reverse(oct))
+        })
+        .unwrap_or_default()
+}
+
+/// The key playing MIDI note `note` on a melody track at `octave`, and whether the note had to
+/// be moved by octaves to fit
+///
+/// Notes below the track go to its lower octave, notes above it to its upper octave.
+pub(crate) fn fit_key(note: u8, octave: u8) -> (PianoKey, bool) {
+    let key = i16::from(note) - i16::from(OCTAVE_0_NOTE + 12 * octave);
+    let fitted = match key {
+        0..24 => key,
+        ..0 => key.rem_euclid(12),
+        _ => 12 + key.rem_euclid(12),
+    };
+    // Within 0..24
+    #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    (fitted as PianoKey, fitted != key)
+}
+
+/// Press `key` in `event`. Returns `true` if it was already pressed, merging two notes.
+pub(crate) const fn press(event: &mut Event, key: PianoKey) -> bool {
+    let merged = event.key_down(key);
+    event.set_key_down(key);
+    merged
+}
+
+/// The median of `lengths` as a note length, and how many lengths differ from it by more
+/// than `tolerance`
+pub(crate) fn median_length(mut lengths: Vec<u64>, tolerance: u64) -> (u16, usize) {
+    lengths.sort_unstable();
+    let Some(&median) = lengths.get(lengths.len() / 2) else {
+        return (0, 0);
+    };
+    let len = u16::try_from(median.max(1)).unwrap_or(u16::MAX);
+    let differing = lengths
+        .iter()
+        .filter(|&&l| l.abs_diff(u64::from(len)) > tolerance)
+        .count();
+    (len, differing)
+}
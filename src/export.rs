@@ -0,0 +1,3 @@
+//! Offline rendering of songs into audio files
+
+pub mod wav;
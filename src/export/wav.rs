@@ -0,0 +1,309 @@
+//! Rendering songs into RIFF WAVE files
+
+use {
+    crate::{Clipping, DrumKit, Fade, Interpolation, LoopMode, Player, Song, Timing},
+    alloc::{vec, vec::Vec},
+    std::io::{self, Write},
+};
+
+/// Sample format of the rendered WAVE file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// 16 bit signed integer PCM
+    I16,
+    /// 32 bit IEEE floating point
+    F32,
+}
+
+impl SampleFormat {
+    const fn bytes_per_sample(self) -> u16 {
+        match self {
+            Self::I16 => 2,
+            Self::F32 => 4,
+        }
+    }
+}
+
+/// Options for rendering a song into a WAVE file
+#[derive(Debug, Clone)]
+pub struct WavOptions {
+    /// Sample rate of the output in Hz
+    pub sample_rate: u32,
+    /// Sample format of the output
+    ///
+    /// 16 bit output is mixed like the original player, while 32 bit float output is mixed
+    /// on a floating point mix bus (see [`Player::render_next_f32`]).
+    pub format: SampleFormat,
+    /// How floating point output is clipped
+    pub clipping: Clipping,
+    /// How many times the repeat range of the song is played (0 is treated as 1)
+    pub loops: u32,
+    /// How many milliseconds to keep rendering after the last event, to let notes ring out
+    pub tail_ms: u32,
+    /// Fade-out during the last loop (see [`Player::fade`])
+    pub fade: Option<Fade>,
+    /// Waveform interpolation of the melody tracks
+    pub interpolation: Interpolation,
+    /// How event durations are rounded to whole samples
+    pub timing: Timing,
+    /// Samples played by the percussion track (see [`Player::set_drum_kit`])
+    pub drum_kit: DrumKit,
+}
+
+impl Default for WavOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44_100,
+            format: SampleFormat::I16,
+            clipping: Clipping::None,
+            loops: 1,
+            tail_ms: 0,
+            fade: None,
+            interpolation: Interpolation::Nearest,
+            timing: Timing::Truncated,
+            drum_kit: DrumKit::default(),
+        }
+    }
+}
+
+/// Render `song` according to `opts`, and write it as a WAVE file to `writer`
+///
+/// # Errors
+///
+/// - If writing to `writer` fails
+/// - If the rendered audio is too long to fit into a WAVE file
+pub fn write<W: Write>(song: &Song, opts: &WavOptions, mut writer: W) -> io::Result<()> {
+    let mut render = Render::new(song, opts)?;
+    write_header(&mut writer, opts, render.data_len)?;
+    let mut bytes = Vec::with_capacity(CHUNK_FRAMES * 2 * 4);
+    render.for_each_chunk(|player, n_frames, in_song| {
+        render_chunk(player, opts.format, n_frames, in_song, &mut bytes);
+        writer.write_all(&bytes)?;
+        bytes.clear();
+        Ok(())
+    })
+}
+
+/// Render each track of `song` separately according to `opts`, and write them as WAVE files
+/// to `writers`, indexed by [`TrackId::index`](crate::TrackId::index)
+///
+/// The tracks are rendered like [`Player::next_stems`] renders them. [`WavOptions::clipping`]
+/// doesn't apply to the stems.
+///
+/// # Errors
+///
+/// - If writing to any of `writers` fails
+/// - If the rendered audio is too long to fit into a WAVE file
+pub fn write_stems<W: Write>(
+    song: &Song,
+    opts: &WavOptions,
+    mut writers: [W; 4],
+) -> io::Result<()> {
+    let mut render = Render::new(song, opts)?;
+    for writer in &mut writers {
+        write_header(writer, opts, render.data_len)?;
+    }
+    let mut bytes: [_; 4] = core::array::from_fn(|_| Vec::with_capacity(CHUNK_FRAMES * 2 * 4));
+    render.for_each_chunk(|player, n_frames, in_song| {
+        render_stems_chunk(player, opts.format, n_frames, in_song, &mut bytes);
+        for (writer, bytes) in writers.iter_mut().zip(&mut bytes) {
+            writer.write_all(bytes)?;
+            bytes.clear();
+        }
+        Ok(())
+    })
+}
+
+/// A player set up to render a song according to some [`WavOptions`]
+struct Render {
+    player: Player,
+    /// Frames until the end of the song
+    song_frames: u64,
+    /// Frames including the tail
+    total_frames: u64,
+    /// Length of the data chunk in bytes
+    data_len: u32,
+}
+
+impl Render {
+    fn new(song: &Song, opts: &WavOptions) -> io::Result<Self> {
+        let mut player = Player::from_song(song.clone(), opts.sample_rate);
+        player.clipping = opts.clipping;
+        player.loop_mode = LoopMode::Times(opts.loops.saturating_sub(1));
+        player.fade = opts.fade;
+        player.set_interpolation(opts.interpolation);
+        player.timing = opts.timing;
+        player.set_drum_kit(opts.drum_kit.clone());
+        let song_frames = player.duration().unwrap_or_default();
+        let tail_frames = u64::from(opts.sample_rate) * u64::from(opts.tail_ms) / 1000;
+        let total_frames = song_frames + tail_frames;
+        let data_len = total_frames
+            .checked_mul(u64::from(CHANNELS * opts.format.bytes_per_sample()))
+            // Leave room for the header, as the RIFF chunk length has to fit as well
+            .filter(|&len| len <= u64::from(u32::MAX - MAX_HEADER_LEN))
+            .and_then(|len| u32::try_from(len).ok())
+            .ok_or_else(|| io::Error::other("Rendered audio is too long for a WAVE file"))?;
+        Ok(Self {
+            player,
+            song_frames,
+            total_frames,
+            data_len,
+        })
+    }
+    /// Call `f` with the number of frames to render next, and whether they are within the song,
+    /// until all frames are rendered
+    fn for_each_chunk(
+        &mut self,
+        mut f: impl FnMut(&mut Player, usize, bool) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut written = 0;
+        while written < self.total_frames {
+            // Chunks end at the end of the song, so they never contain both song and tail frames
+            let in_song = written < self.song_frames;
+            let end = if in_song {
+                self.song_frames
+            } else {
+                self.total_frames
+            };
+            // Bounded by `CHUNK_FRAMES`
+            #[expect(clippy::cast_possible_truncation)]
+            let n_frames = (end - written).min(CHUNK_FRAMES as u64) as usize;
+            f(&mut self.player, n_frames, in_song)?;
+            written += n_frames as u64;
+        }
+        Ok(())
+    }
+}
+
+const CHANNELS: u16 = 2;
+const CHUNK_FRAMES: usize = 2048;
+const MAX_HEADER_LEN: u32 = 58;
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+
+/// Render `n_frames` frames, and append them to `out` encoded in `format`
+///
+/// Unless `advance` is true, only the currently playing notes are rendered, and no new
+/// events are processed.
+fn render_chunk(
+    player: &mut Player,
+    format: SampleFormat,
+    n_frames: usize,
+    advance: bool,
+    out: &mut Vec<u8>,
+) {
+    match format {
+        SampleFormat::I16 => {
+            let mut buf = [0; CHUNK_FRAMES * 2];
+            let buf = &mut buf[..n_frames * 2];
+            if advance {
+                player.render_next(buf);
+            } else {
+                for frame in buf.as_chunks_mut().0 {
+                    *frame = player.next_sample();
+                }
+            }
+            for s in buf {
+                out.extend_from_slice(&s.to_le_bytes());
+            }
+        }
+        SampleFormat::F32 => {
+            let mut buf = [0.0; CHUNK_FRAMES * 2];
+            let buf = &mut buf[..n_frames * 2];
+            if advance {
+                player.render_next_f32(buf);
+            } else {
+                for frame in buf.as_chunks_mut().0 {
+                    *frame = player.next_sample_f32();
+                }
+            }
+            for s in buf {
+                out.extend_from_slice(&s.to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Like [`render_chunk`], but renders each track separately into the buffer of `out` at its
+/// [`TrackId::index`](crate::TrackId::index)
+fn render_stems_chunk(
+    player: &mut Player,
+    format: SampleFormat,
+    n_frames: usize,
+    advance: bool,
+    out: &mut [Vec<u8>; 4],
+) {
+    match format {
+        SampleFormat::I16 => {
+            let mut buf = vec![[[0; 2]; 4]; n_frames];
+            if advance {
+                player.render_stems(&mut buf);
+            } else {
+                for stems in &mut buf {
+                    *stems = player.next_stems();
+                }
+            }
+            for stems in buf {
+                for (out, stem) in out.iter_mut().zip(stems) {
+                    for s in stem {
+                        out.extend_from_slice(&s.to_le_bytes());
+                    }
+                }
+            }
+        }
+        SampleFormat::F32 => {
+            let mut buf = vec![[[0.0; 2]; 4]; n_frames];
+            if advance {
+                player.render_stems_f32(&mut buf);
+            } else {
+                for stems in &mut buf {
+                    *stems = player.next_stems_f32();
+                }
+            }
+            for stems in buf {
+                for (out, stem) in out.iter_mut().zip(stems) {
+                    for s in stem {
+                        out.extend_from_slice(&s.to_le_bytes());
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn write_header<W: Write>(writer: &mut W, opts: &WavOptions, data_len: u32) -> io::Result<()> {
+    let bytes_per_sample = opts.format.bytes_per_sample();
+    let block_align = CHANNELS * bytes_per_sample;
+    let (format_tag, fmt_len, fact_len) = match opts.format {
+        SampleFormat::I16 => (FORMAT_PCM, 16, 0),
+        // Non-PCM formats carry an extension size field, and require a fact chunk
+        SampleFormat::F32 => (FORMAT_IEEE_FLOAT, 18, 12),
+    };
+    let riff_len = 4 + (8 + fmt_len) + fact_len + 8 + data_len;
+    let mut header = Vec::with_capacity(64);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&riff_len.to_le_bytes());
+    header.extend_from_slice(b"WAVE");
+    header.extend_from_slice(b"fmt ");
+    header.extend_from_slice(&fmt_len.to_le_bytes());
+    header.extend_from_slice(&format_tag.to_le_bytes());
+    header.extend_from_slice(&CHANNELS.to_le_bytes());
+    header.extend_from_slice(&opts.sample_rate.to_le_bytes());
+    header.extend_from_slice(
+        &opts
+            .sample_rate
+            .saturating_mul(u32::from(block_align))
+            .to_le_bytes(),
+    );
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&(bytes_per_sample * 8).to_le_bytes());
+    if opts.format == SampleFormat::F32 {
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(b"fact");
+        header.extend_from_slice(&4u32.to_le_bytes());
+        header.extend_from_slice(&(data_len / u32::from(block_align)).to_le_bytes());
+    }
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+    writer.write_all(&header)
+}
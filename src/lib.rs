@@ -1,7 +1,19 @@
 //! Player for Pixel's Piyo Piyo (PMD) music format.
 //!
 //! Based on <https://github.com/alula/piyoplay>
+//!
+//! # Features
+//!
+//! - `std` (enabled by default): Writing songs and exports to `std::io::Write`, and float
+//!   math from the standard library. Without it, the crate only needs `alloc`, and songs and
+//!   exports are converted into byte vectors instead.
+//! - `libm`: Float math from `libm`, which is required without `std`. It may round slightly
+//!   differently than the standard library, which is used instead when both are enabled.
+//! - `serde`: `Serialize` and `Deserialize` for [`Song`] and the types it consists of.
+//!   Deserialized songs aren't checked like [`Song::load`] checks files, so they may have
+//!   problems that [`Song::validate`] reports. [`Player`] plays them either way.
 
+#![no_std]
 #![forbid(unsafe_code)]
 #![warn(
     missing_docs,
@@ -15,15 +27,34 @@
 )]
 
 pub use crate::{
-    player::Player,
-    song::{LoadError, Song},
+    player::{
+        Clipping, Fade, FadeCurve, LoopMode, PlaybackEvent, PlaybackEventKind, Player, Timing,
+        TrackControls, sample_from_f32,
+    },
+    song::{Diagnostic, Field, LoadError, Location, MelodyField, Problem, Severity, Song},
     track::{
-        DRUM_SAMPLES, Event, MelodyTrack, N_KEYS, PercussionTrack, PianoKey, Track, piano_keys,
+        DRUM_SAMPLES, DrumKit, DrumSample, DrumSampleError, Event, Interpolation, MelodyTrack,
+        N_KEYS, Pan, PercussionTrack, PianoKey, Track, TrackId, Voices, piano_keys,
     },
 };
 
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!("either the `std` or the `libm` feature is required for float math");
+
+mod convert;
+#[cfg(feature = "std")]
+pub mod export;
+mod math;
+pub mod midi;
+pub mod organya;
 mod player;
 mod read_cursor;
+#[cfg(feature = "serde")]
+mod serde_array;
 mod song;
 mod track;
 
@@ -0,0 +1,68 @@
+//! Float math, which comes from `libm` without the `std` feature
+//!
+//! The functions follow the naming of `libm`: the `f32` versions end in `f`.
+
+pub(crate) use self::imp::*;
+
+#[cfg(feature = "std")]
+mod imp {
+    pub(crate) fn pow(x: f64, y: f64) -> f64 {
+        x.powf(y)
+    }
+    pub(crate) fn powf(x: f32, y: f32) -> f32 {
+        x.powf(y)
+    }
+    pub(crate) fn powi(x: f64, n: i32) -> f64 {
+        x.powi(n)
+    }
+    pub(crate) fn sin(x: f64) -> f64 {
+        x.sin()
+    }
+    pub(crate) fn cos(x: f64) -> f64 {
+        x.cos()
+    }
+    pub(crate) fn sincos(x: f64) -> (f64, f64) {
+        x.sin_cos()
+    }
+    pub(crate) fn tanhf(x: f32) -> f32 {
+        x.tanh()
+    }
+    pub(crate) fn sqrt(x: f64) -> f64 {
+        x.sqrt()
+    }
+    pub(crate) fn log10(x: f64) -> f64 {
+        x.log10()
+    }
+    pub(crate) fn floor(x: f64) -> f64 {
+        x.floor()
+    }
+    pub(crate) fn ceil(x: f64) -> f64 {
+        x.ceil()
+    }
+    pub(crate) fn round(x: f64) -> f64 {
+        x.round()
+    }
+    pub(crate) fn fract(x: f64) -> f64 {
+        x.fract()
+    }
+    pub(crate) fn fma(x: f64, y: f64, z: f64) -> f64 {
+        x.mul_add(y, z)
+    }
+    pub(crate) fn fmaf(x: f32, y: f32, z: f32) -> f32 {
+        x.mul_add(y, z)
+    }
+}
+
+#[cfg(all(not(feature = "std"), feature = "libm"))]
+mod imp {
+    pub(crate) use libm::{
+        ceil, cos, floor, fma, fmaf, log10, pow, powf, round, sin, sincos, sqrt, tanhf,
+    };
+
+    pub(crate) fn powi(x: f64, n: i32) -> f64 {
+        pow(x, f64::from(n))
+    }
+    pub(crate) fn fract(x: f64) -> f64 {
+        x - libm::trunc(x)
+    }
+}
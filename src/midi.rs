@@ -0,0 +1,53 @@
+//! Conversion between songs and Standard MIDI Files
+
+#[cfg(feature = "std")]
+pub use self::export::write;
+pub use self::export::{WriteError, to_bytes};
+pub use self::import::{ConversionReport, Loss, ReadError, read};
+
+use crate::{N_KEYS, Pan, PianoKey, convert::OCTAVE_0_NOTE};
+
+/// The percussion track is played on the General MIDI percussion channel (channel 10)
+const PERCUSSION_CHANNEL: u8 = 9;
+
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CC_VOLUME: u8 = 7;
+const CC_PAN: u8 = 10;
+
+mod export;
+mod import;
+
+/// General MIDI percussion note for each percussion key
+///
+/// This follows the built-in samples of [`DRUM_SAMPLES`](crate::DRUM_SAMPLES): bass drum 1,
+/// bass drum 2, snare, closed hi-hat, open hi-hat, and crash cymbal for the rest.
+pub const DRUM_NOTES: [u8; N_KEYS as usize] = [
+    36, 36, 35, 35, 38, 38, 38, 38, 42, 42, 46, 46, 49, 49, 49, 49, 49, 49, 49, 49, 49, 49, 49, 49,
+];
+
+/// The MIDI note number of `key` played by a melody track at `octave`
+///
+/// Key 0 at octave 0 is C1 (note 24). Notes above the MIDI range are moved down by octaves.
+#[must_use]
+pub fn melody_note(key: PianoKey, octave: u8) -> u8 {
+    let mut note = u16::from(OCTAVE_0_NOTE) + 12 * u16::from(octave) + u16::from(key);
+    while note > 127 {
+        note -= 12;
+    }
+    // Reduced to the MIDI range above
+    #[expect(clippy::cast_possible_truncation)]
+    {
+        note as u8
+    }
+}
+
+/// Pan controller value of `pan`, from 0 (left) over 64 (center) to 127 (right)
+fn pan_cc(pan: Pan) -> u8 {
+    let idx = u16::from(pan.to_raw() - 1);
+    // At most 127
+    #[expect(clippy::cast_possible_truncation)]
+    {
+        ((idx * 127 + 3) / 6) as u8
+    }
+}
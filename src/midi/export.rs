@@ -0,0 +1,278 @@
+use {
+    super::{
+        CC_PAN, CC_VOLUME, DRUM_NOTES, NOTE_OFF, NOTE_ON, PERCUSSION_CHANNEL, melody_note, pan_cc,
+    },
+    crate::{DrumKit, Event, Pan, PianoKey, Song, convert::VOICE_RATE, math},
+    alloc::{format, vec, vec::Vec},
+};
+
+/// Resolution of the exported file in ticks per quarter note
+const TICKS_PER_QUARTER: u16 = 480;
+/// Events are laid out as sixteenth notes
+const TICKS_PER_EVENT: u64 = TICKS_PER_QUARTER as u64 / 4;
+/// Longest event wait that can be expressed as a MIDI tempo (24 bit microseconds per quarter)
+const MAX_EVENT_WAIT_MS: u32 = 0xFF_FFFF / 4000;
+/// Velocity of melody notes, as the track volume is expressed with channel volume instead
+const MELODY_VELOCITY: u8 = 100;
+
+const CONTROL_CHANGE: u8 = 0xB0;
+
+/// Convert `song` into a type 1 Standard MIDI File
+///
+/// The first track holds the tempo and the repeat range (as `loopStart` and `loopEnd` markers),
+/// and ends at the end of the song. It's followed by one track for each melody track on
+/// channels 1 to 3, and the percussion track on channel 10. Each event of the song is a
+/// sixteenth note.
+///
+/// # Errors
+///
+/// - If a track is longer than a MIDI track chunk can be
+pub fn to_bytes(song: &Song) -> Result<Vec<u8>, WriteError> {
+    let wait_ms = song.event_wait_ms.clamp(1, MAX_EVENT_WAIT_MS);
+    let mut tracks = vec![conductor_track(song, wait_ms)];
+    for (channel, track) in (0..).zip(&song.melody_tracks) {
+        tracks.push(note_track(
+            &format!("Melody {}", channel + 1),
+            channel,
+            &track.base.events,
+            volume_cc(track.base.vol, 10),
+            |key| {
+                let duration = note_ticks(u64::from(track.len), wait_ms);
+                (melody_note(key, track.octave), MELODY_VELOCITY, duration)
+            },
+        ));
+    }
+    let drums = &song.percussion_track;
+    // Note lengths follow the built-in samples, which the drum notes are chosen after
+    let kit = DrumKit::default();
+    // Every second key is played quieter, at 70% of the track volume
+    let vol = volume_cc(drums.base.vol, 10);
+    let low_vol = volume_cc(drums.base.vol, 7);
+    tracks.push(note_track(
+        "Percussion",
+        PERCUSSION_CHANNEL,
+        &drums.base.events,
+        vol,
+        |key| {
+            let velocity = if key.is_multiple_of(2) {
+                127
+            } else {
+                // Bounded by the volume of even keys, which is at most 127
+                #[expect(clippy::cast_possible_truncation)]
+                {
+                    (u16::from(low_vol) * 127 / u16::from(vol.max(1))) as u8
+                }
+            };
+            let len = kit.sample(key).voice_len();
+            (
+                DRUM_NOTES[usize::from(key)],
+                velocity,
+                note_ticks(len, wait_ms),
+            )
+        },
+    ));
+    let mut out = Vec::new();
+    out.extend_from_slice(b"MThd");
+    out.extend_from_slice(&6u32.to_be_bytes());
+    out.extend_from_slice(&1u16.to_be_bytes());
+    // There are at most 5 tracks
+    #[expect(clippy::cast_possible_truncation)]
+    out.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+    out.extend_from_slice(&TICKS_PER_QUARTER.to_be_bytes());
+    for track in tracks {
+        out.extend_from_slice(b"MTrk");
+        let len = u32::try_from(track.len()).map_err(|_| WriteError::TrackTooLong)?;
+        out.extend_from_slice(&len.to_be_bytes());
+        out.extend_from_slice(&track);
+    }
+    Ok(out)
+}
+
+/// Convert `song` into a type 1 Standard MIDI File like [`to_bytes`], and write it to `writer`
+///
+/// # Errors
+///
+/// - If writing to `writer` fails
+/// - If a track is longer than a MIDI track chunk can be
+#[cfg(feature = "std")]
+pub fn write<W: std::io::Write>(song: &Song, mut writer: W) -> std::io::Result<()> {
+    writer.write_all(&to_bytes(song).map_err(std::io::Error::other)?)
+}
+
+/// Error that can happen when converting a song into a MIDI file
+#[derive(Debug)]
+pub enum WriteError {
+    /// A track has more than 4 GiB of messages
+    TrackTooLong,
+}
+
+impl core::fmt::Display for WriteError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            WriteError::TrackTooLong => f.write_str("MIDI track is too long"),
+        }
+    }
+}
+
+impl core::error::Error for WriteError {}
+
+/// A MIDI message (or meta event) at an absolute tick
+struct TimedMessage {
+    tick: u64,
+    /// Messages at the same tick are ordered by this: note offs, then controllers, then note ons
+    order: u8,
+    bytes: Vec<u8>,
+}
+
+/// Encode `messages` into the contents of a track chunk, including the end of track event
+///
+/// The end of track event is at `end`, or at the last message if that is later.
+fn encode_track(mut messages: Vec<TimedMessage>, end: u64) -> Vec<u8> {
+    messages.sort_by_key(|msg| (msg.tick, msg.order));
+    let mut out = Vec::new();
+    let mut tick = 0;
+    for msg in &messages {
+        write_vlq(&mut out, msg.tick - tick);
+        out.extend_from_slice(&msg.bytes);
+        tick = msg.tick;
+    }
+    write_vlq(&mut out, end.saturating_sub(tick));
+    out.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+    out
+}
+
+/// Write a variable length quantity, as used for delta times and meta event lengths
+fn write_vlq(out: &mut Vec<u8>, value: u64) {
+    // Delta times are limited to 28 bits
+    let value = value.min(0x0FFF_FFFF);
+    let mut shift = 21;
+    while shift > 0 && value >> shift == 0 {
+        shift -= 7;
+    }
+    while shift > 0 {
+        out.push(0x80 | ((value >> shift) & 0x7F) as u8);
+        shift -= 7;
+    }
+    out.push((value & 0x7F) as u8);
+}
+
+fn meta_event(kind: u8, data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0xFF, kind];
+    write_vlq(&mut bytes, data.len() as u64);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+fn conductor_track(song: &Song, wait_ms: u32) -> Vec<u8> {
+    let us_per_quarter = (wait_ms * 4000).to_be_bytes();
+    let mut messages = vec![
+        TimedMessage {
+            tick: 0,
+            order: 0,
+            bytes: meta_event(0x51, &us_per_quarter[1..]),
+        },
+        TimedMessage {
+            tick: 0,
+            order: 0,
+            // 4/4, 24 clocks per metronome click, 8 32nd notes per quarter
+            bytes: meta_event(0x58, &[4, 2, 24, 8]),
+        },
+    ];
+    let range = &song.repeat_range;
+    for (event, name) in [(range.start, "loopStart"), (range.end, "loopEnd")] {
+        messages.push(TimedMessage {
+            tick: u64::from(event) * TICKS_PER_EVENT,
+            order: 1,
+            bytes: meta_event(0x06, name.as_bytes()),
+        });
+    }
+    // Marks the end of the song, which can be past the repeat range
+    let n_events = song
+        .melody_tracks
+        .iter()
+        .map(|track| &track.base)
+        .chain([&song.percussion_track.base])
+        .map(|base| base.events.len() as u64)
+        .max()
+        .unwrap_or(0);
+    encode_track(messages, n_events * TICKS_PER_EVENT)
+}
+
+/// Build a track playing `events` on `channel`
+///
+/// `note` returns the note number, velocity, and duration in ticks of each key.
+fn note_track(
+    name: &str,
+    channel: u8,
+    events: &[Event],
+    volume: u8,
+    note: impl Fn(PianoKey) -> (u8, u8, u64),
+) -> Vec<u8> {
+    let controller = |tick, cc, value| TimedMessage {
+        tick,
+        order: 1,
+        bytes: vec![CONTROL_CHANGE | channel, cc, value],
+    };
+    let mut messages = vec![
+        TimedMessage {
+            tick: 0,
+            order: 0,
+            bytes: meta_event(0x03, name.as_bytes()),
+        },
+        controller(0, CC_VOLUME, volume),
+        // Tracks start out centered
+        controller(0, CC_PAN, pan_cc(Pan::Center)),
+    ];
+    // For each note number, the tick its note off is scheduled for.
+    // Several keys can map to the same note (like the percussion keys), so this is tracked
+    // per note rather than per key.
+    let mut playing: [Option<u64>; 128] = [None; _];
+    let note_off = |note, tick| TimedMessage {
+        tick,
+        order: 0,
+        bytes: vec![NOTE_OFF | channel, note, 0],
+    };
+    for (idx, event) in (0..).zip(events) {
+        let tick = idx * TICKS_PER_EVENT;
+        if let Some(pan) = event.pan_pos() {
+            messages.push(controller(tick, CC_PAN, pan_cc(pan)));
+        }
+        for key in event.keys_down() {
+            let (number, velocity, duration) = note(key);
+            // Playing a note again restarts it
+            if let Some(end) = playing[usize::from(number)].take() {
+                messages.push(note_off(number, end.min(tick)));
+            }
+            messages.push(TimedMessage {
+                tick,
+                order: 2,
+                bytes: vec![NOTE_ON | channel, number, velocity],
+            });
+            playing[usize::from(number)] = Some(tick + duration);
+        }
+    }
+    for (number, end) in (0..).zip(playing) {
+        if let Some(end) = end {
+            messages.push(note_off(number, end));
+        }
+    }
+    encode_track(messages, 0)
+}
+
+/// Length of a note lasting `len` voice samples, in ticks rounded to nearest
+fn note_ticks(len: u64, wait_ms: u32) -> u64 {
+    let den = VOICE_RATE * u64::from(wait_ms);
+    ((len * TICKS_PER_EVENT * 1000 + den / 2) / den).max(1)
+}
+
+/// Channel volume for a track volume of `vol`, scaled by `tenths` / 10 like the player does
+fn volume_cc(vol: u16, tenths: i32) -> u8 {
+    let vol = ((tenths * i32::from(vol) / 10) - 300) * 8;
+    // Volumes are in hundredths of decibels
+    let gain = math::pow(10.0, f64::from(vol) / 2000.0);
+    // Clamped to the MIDI range
+    #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    {
+        math::round(gain * 127.0).clamp(0.0, 127.0) as u8
+    }
+}
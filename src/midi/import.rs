@@ -0,0 +1,779 @@
+use {
+    super::{CC_PAN, CC_VOLUME, DRUM_NOTES, NOTE_OFF, NOTE_ON, PERCUSSION_CHANNEL, pan_cc},
+    crate::{
+        Event, MelodyTrack, N_KEYS, Pan, PercussionTrack, PianoKey, Song, TrackId,
+        convert::{MAX_EVENTS, VOICE_RATE, best_octave, fit_key, median_length, press},
+        math,
+        read_cursor::ReadCursor,
+    },
+    alloc::{collections::BTreeMap, vec::Vec},
+};
+
+/// Tempo of files without a tempo event (120 beats per minute)
+const DEFAULT_US_PER_QUARTER: u32 = 500_000;
+/// Channel volume of channels without a volume controller
+const DEFAULT_CHANNEL_VOLUME: u8 = 100;
+/// Drum notes below this velocity are played on the quieter odd percussion keys
+const QUIET_VELOCITY: u8 = 96;
+
+/// Read a Standard MIDI File, and convert it into a song
+///
+/// Notes are quantized onto the event grid, using the coarsest of the common note divisions
+/// (from sixteenth notes down to 128th notes and triplets) that fits every note, or sixteenth
+/// notes if none does. The three melodic channels with the most notes become the melody
+/// tracks, with their octave chosen to fit the most notes, and channel 10 becomes the
+/// percussion track. The repeat range is taken from `loopStart` and `loopEnd` markers, like
+/// the ones written by [`to_bytes`](super::to_bytes).
+///
+/// Everything that couldn't be converted exactly is listed in the returned report.
+///
+/// # Errors
+///
+/// - If the data isn't a type 0 or type 1 MIDI file with metrical timing
+/// - If the file is truncated or contains an invalid event
+/// - If the song would be longer than a million events
+pub fn read(data: &[u8]) -> Result<(Song, ConversionReport), ReadError> {
+    convert(parse(data)?)
+}
+
+/// The conversion losses of [`read`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConversionReport {
+    /// What couldn't be converted exactly, in the order it was encountered
+    pub losses: Vec<Loss>,
+}
+
+impl ConversionReport {
+    /// Whether the song plays exactly like the MIDI file (as far as the format allows)
+    #[must_use]
+    pub const fn is_lossless(&self) -> bool {
+        self.losses.is_empty()
+    }
+}
+
+/// Something that couldn't be converted exactly when importing a MIDI file
+///
+/// Channel numbers are zero based, so General MIDI percussion is on channel 9.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Loss {
+    /// Only the first tempo is used, and the following tempo changes were ignored
+    TempoChanges {
+        /// How many tempo changes were ignored
+        ignored: usize,
+    },
+    /// The event duration was rounded to whole milliseconds
+    TempoRounded {
+        /// The exact duration of an event in microseconds
+        us_per_event: u64,
+        /// The rounded duration used for [`Song::event_wait_ms`]
+        event_wait_ms: u32,
+    },
+    /// Notes that didn't start on the event grid were moved to the nearest event
+    NotesQuantized {
+        /// How many notes were moved
+        notes: usize,
+    },
+    /// A melodic channel was dropped, as there are only 3 melody tracks
+    ChannelDropped {
+        /// The dropped channel
+        channel: u8,
+        /// How many notes the channel had
+        notes: usize,
+    },
+    /// Notes outside of the two octaves a track can play were moved by whole octaves to fit
+    NotesFolded {
+        /// The track the notes were moved on
+        track: TrackId,
+        /// How many notes were moved
+        notes: usize,
+    },
+    /// Notes were merged with another note of the same key in the same event
+    NotesMerged {
+        /// The track the notes were merged on
+        track: TrackId,
+        /// How many notes were lost by merging
+        notes: usize,
+    },
+    /// Notes play for the note length of the track, which differs from their own length
+    LengthsUnified {
+        /// The track with the notes
+        track: TrackId,
+        /// How many notes differ from the note length by more than half an event
+        notes: usize,
+    },
+    /// Notes differ in velocity, but all of them play at the track volume
+    VelocitiesIgnored {
+        /// The track with the notes
+        track: TrackId,
+        /// How many notes the track has
+        notes: usize,
+    },
+    /// Pan changes were rounded to one of the pan positions, or were overridden by another
+    /// pan change within the same event
+    PanApproximated {
+        /// The track with the pan changes
+        track: TrackId,
+        /// How many pan changes were affected
+        changes: usize,
+    },
+    /// Drum notes were played with a similar drum
+    DrumsSubstituted {
+        /// The General MIDI percussion note
+        note: u8,
+        /// How many notes were substituted
+        notes: usize,
+    },
+    /// Drum notes without a similar drum were dropped
+    DrumsDropped {
+        /// The General MIDI percussion note
+        note: u8,
+        /// How many notes were dropped
+        notes: usize,
+    },
+    /// Messages without an equivalent, like program changes, pitch bends, volume changes
+    /// after the initial volume, and most controllers, were ignored
+    MessagesIgnored {
+        /// How many messages were ignored
+        messages: usize,
+    },
+}
+
+impl core::fmt::Display for Loss {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Loss::TempoChanges { ignored } => write!(f, "{ignored} tempo changes ignored"),
+            Loss::TempoRounded {
+                us_per_event,
+                event_wait_ms,
+            } => write!(
+                f,
+                "Event duration of {us_per_event} µs rounded to {event_wait_ms} ms"
+            ),
+            Loss::NotesQuantized { notes } => write!(f, "{notes} notes moved onto the event grid"),
+            Loss::ChannelDropped { channel, notes } => write!(
+                f,
+                "Channel {} dropped with {notes} notes (only 3 melody tracks are available)",
+                channel + 1
+            ),
+            Loss::NotesFolded { track, notes } => {
+                write!(f, "{notes} notes moved by octaves to fit {track}")
+            }
+            Loss::NotesMerged { track, notes } => {
+                write!(f, "{notes} notes merged with another note on {track}")
+            }
+            Loss::LengthsUnified { track, notes } => {
+                write!(f, "{notes} notes changed to the note length of {track}")
+            }
+            Loss::VelocitiesIgnored { track, notes } => {
+                write!(f, "Velocities of {notes} notes ignored on {track}")
+            }
+            Loss::PanApproximated { track, changes } => {
+                write!(f, "{changes} pan changes approximated on {track}")
+            }
+            Loss::DrumsSubstituted { note, notes } => {
+                write!(f, "{notes} drum notes {note} played with a similar drum")
+            }
+            Loss::DrumsDropped { note, notes } => {
+                write!(f, "{notes} drum notes {note} dropped (no similar drum)")
+            }
+            Loss::MessagesIgnored { messages } => {
+                write!(f, "{messages} unsupported messages ignored")
+            }
+        }
+    }
+}
+
+/// Error that can happen when reading a MIDI file
+#[derive(Debug)]
+pub enum ReadError {
+    /// The data doesn't start with a valid `MThd` header chunk
+    InvalidHeader,
+    /// Type 2 (sequential) and unknown formats are not supported
+    UnsupportedFormat(u16),
+    /// SMPTE based timing is not supported
+    UnsupportedTiming,
+    /// End of file was reached prematurely
+    PrematureEof {
+        /// Byte offset of the chunk or event that couldn't be read
+        offset: usize,
+    },
+    /// An event couldn't be interpreted
+    InvalidEvent {
+        /// Byte offset of the event
+        offset: usize,
+    },
+    /// The song would have more events than supported
+    TooLong {
+        /// How many events the song would have
+        events: u64,
+    },
+}
+
+impl core::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ReadError::InvalidHeader => f.write_str("Invalid MIDI header"),
+            ReadError::UnsupportedFormat(format) => {
+                write!(f, "Unsupported MIDI file format {format}")
+            }
+            ReadError::UnsupportedTiming => f.write_str("SMPTE timing is not supported"),
+            ReadError::PrematureEof { offset } => {
+                write!(f, "End of file reached prematurely at offset {offset:#X}")
+            }
+            ReadError::InvalidEvent { offset } => write!(f, "Invalid event at offset {offset:#X}"),
+            ReadError::TooLong { events } => write!(f, "Song is too long ({events} events)"),
+        }
+    }
+}
+
+impl core::error::Error for ReadError {}
+
+struct Note {
+    start: u64,
+    end: u64,
+    channel: u8,
+    key: u8,
+    velocity: u8,
+}
+
+struct ControlChange {
+    tick: u64,
+    channel: u8,
+    controller: u8,
+    value: u8,
+}
+
+/// The parts of a MIDI file that are relevant for conversion
+#[derive(Default)]
+struct Smf {
+    ticks_per_quarter: u16,
+    notes: Vec<Note>,
+    /// Volume and pan controllers
+    controllers: Vec<ControlChange>,
+    /// Tick and microseconds per quarter note of each tempo event
+    tempos: Vec<(u64, u32)>,
+    loop_start: Option<u64>,
+    loop_end: Option<u64>,
+    /// The tick of the last end of track event
+    end: u64,
+    /// The tick of the last end of track event of a track without notes
+    ///
+    /// Conductor tracks end at the end of the song, which can be after the loop end marker.
+    noteless_end: u64,
+    ignored_messages: usize,
+}
+
+fn parse(data: &[u8]) -> Result<Smf, ReadError> {
+    let mut cur = ReadCursor::new(data);
+    if cur.next_bytes() != Some(b"MThd") {
+        return Err(ReadError::InvalidHeader);
+    }
+    let header = cur
+        .next_u32_be()
+        .and_then(|len| cur.next_slice(usize::try_from(len).ok()?))
+        .ok_or(ReadError::InvalidHeader)?;
+    let mut header = ReadCursor::new(header);
+    let (Some(format), Some(_n_tracks), Some(division)) = (
+        header.next_u16_be(),
+        header.next_u16_be(),
+        header.next_u16_be(),
+    ) else {
+        return Err(ReadError::InvalidHeader);
+    };
+    if format > 1 {
+        return Err(ReadError::UnsupportedFormat(format));
+    }
+    if division & 0x8000 != 0 {
+        return Err(ReadError::UnsupportedTiming);
+    }
+    if division == 0 {
+        return Err(ReadError::InvalidHeader);
+    }
+    let mut smf = Smf {
+        ticks_per_quarter: division,
+        ..Smf::default()
+    };
+    while let Some(id) = cur.next_bytes::<4>() {
+        let offset = cur.pos() - 4;
+        let chunk = cur
+            .next_u32_be()
+            .and_then(|len| cur.next_slice(usize::try_from(len).ok()?))
+            .ok_or(ReadError::PrematureEof { offset })?;
+        // Unknown chunks are skipped, as required by the specification
+        if id == b"MTrk" {
+            parse_track(chunk, offset + 8, &mut smf)?;
+        }
+    }
+    Ok(smf)
+}
+
+/// Parse the track chunk `data` starting at byte offset `base`, and add its contents to `smf`
+fn parse_track(data: &[u8], base: usize, smf: &mut Smf) -> Result<(), ReadError> {
+    let mut cur = ReadCursor::new(data);
+    let mut tick = 0;
+    let mut running_status = None;
+    // Notes that have been started, but not yet stopped
+    let mut open_notes: Vec<Note> = Vec::new();
+    let notes_before = smf.notes.len();
+    while cur.pos() < data.len() {
+        let offset = base + cur.pos();
+        let eof = || ReadError::PrematureEof { offset };
+        tick += read_vlq(&mut cur).ok_or_else(eof)?;
+        let byte = cur.next_u8().ok_or_else(eof)?;
+        let (status, first) = match byte {
+            0xFF => {
+                let kind = cur.next_u8().ok_or_else(eof)?;
+                let data = read_vlq(&mut cur)
+                    .and_then(|len| cur.next_slice(usize::try_from(len).ok()?))
+                    .ok_or_else(eof)?;
+                match kind {
+                    0x2F => break,
+                    0x51 => {
+                        if let &[a, b, c] = data {
+                            smf.tempos.push((tick, u32::from_be_bytes([0, a, b, c])));
+                        }
+                    }
+                    // Marker and cue point
+                    0x06 | 0x07 => {
+                        if data.eq_ignore_ascii_case(b"loopStart") {
+                            smf.loop_start.get_or_insert(tick);
+                        } else if data.eq_ignore_ascii_case(b"loopEnd") {
+                            smf.loop_end.get_or_insert(tick);
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            0xF0 | 0xF7 => {
+                read_vlq(&mut cur)
+                    .and_then(|len| cur.next_slice(usize::try_from(len).ok()?))
+                    .ok_or_else(eof)?;
+                continue;
+            }
+            0xF1..=0xFE => return Err(ReadError::InvalidEvent { offset }),
+            0x80..=0xEF => {
+                running_status = Some(byte);
+                (byte, cur.next_u8().ok_or_else(eof)?)
+            }
+            _ => (
+                running_status.ok_or(ReadError::InvalidEvent { offset })?,
+                byte,
+            ),
+        };
+        let second = match status & 0xF0 {
+            0xC0 | 0xD0 => 0,
+            _ => cur.next_u8().ok_or_else(eof)?,
+        };
+        let (channel, key, value) = (status & 0x0F, first & 0x7F, second & 0x7F);
+        match status & 0xF0 {
+            NOTE_ON if value != 0 => open_notes.push(Note {
+                start: tick,
+                end: tick,
+                channel,
+                key,
+                velocity: value,
+            }),
+            NOTE_OFF | NOTE_ON => {
+                if let Some(idx) = open_notes
+                    .iter()
+                    .position(|note| note.channel == channel && note.key == key)
+                {
+                    let mut note = open_notes.remove(idx);
+                    note.end = tick;
+                    smf.notes.push(note);
+                }
+            }
+            0xB0 if key == CC_VOLUME || key == CC_PAN => smf.controllers.push(ControlChange {
+                tick,
+                channel,
+                controller: key,
+                value,
+            }),
+            _ => smf.ignored_messages += 1,
+        }
+    }
+    // Notes that are never stopped last until the end of the track
+    for mut note in open_notes {
+        note.end = tick;
+        smf.notes.push(note);
+    }
+    smf.end = smf.end.max(tick);
+    if smf.notes.len() == notes_before {
+        smf.noteless_end = smf.noteless_end.max(tick);
+    }
+    Ok(())
+}
+
+/// Read a variable length quantity of at most 4 bytes
+fn read_vlq(cur: &mut ReadCursor) -> Option<u64> {
+    let mut value = 0;
+    for _ in 0..4 {
+        let byte = cur.next_u8()?;
+        value = (value << 7) | u64::from(byte & 0x7F);
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Some(value)
+}
+
+fn convert(mut smf: Smf) -> Result<(Song, ConversionReport), ReadError> {
+    let mut losses = Vec::new();
+    let ticks_per_quarter = u64::from(smf.ticks_per_quarter);
+    // Tempo events of different tracks are merged, so they need to be sorted
+    smf.tempos.sort_by_key(|&(tick, _)| tick);
+    let us_per_quarter = smf
+        .tempos
+        .first()
+        .map_or(DEFAULT_US_PER_QUARTER, |&(_, tempo)| tempo);
+    let tempo_changes = smf.tempos.windows(2).filter(|w| w[0].1 != w[1].1).count();
+    if tempo_changes > 0 {
+        losses.push(Loss::TempoChanges {
+            ignored: tempo_changes,
+        });
+    }
+    // Use the coarsest grid that fits every note, or sixteenth notes if none does
+    let ticks_per_event = [4, 8, 12, 16, 24, 32]
+        .into_iter()
+        .filter(|&div| ticks_per_quarter.is_multiple_of(div))
+        .map(|div| ticks_per_quarter / div)
+        .find(|&ticks| smf.notes.iter().all(|n| n.start.is_multiple_of(ticks)))
+        .unwrap_or((ticks_per_quarter / 4).max(1));
+    let event_of = |tick: u64| (tick + ticks_per_event / 2) / ticks_per_event;
+    let off_grid = smf
+        .notes
+        .iter()
+        .filter(|n| !n.start.is_multiple_of(ticks_per_event))
+        .count();
+    if off_grid > 0 {
+        losses.push(Loss::NotesQuantized { notes: off_grid });
+    }
+    let us_per_event = u64::from(us_per_quarter) * ticks_per_event / ticks_per_quarter;
+    let event_wait_ms = u32::try_from(((us_per_event + 500) / 1000).max(1)).unwrap_or(u32::MAX);
+    if us_per_event != u64::from(event_wait_ms) * 1000 {
+        losses.push(Loss::TempoRounded {
+            us_per_event,
+            event_wait_ms,
+        });
+    }
+
+    let events_needed = smf.notes.iter().map(|n| event_of(n.start) + 1).max();
+    let n_events = match smf.loop_end {
+        Some(end) => event_of(end),
+        None => smf.end.div_ceil(ticks_per_event),
+    }
+    .max(event_of(smf.noteless_end))
+    .max(events_needed.unwrap_or(0))
+    .max(1);
+    if n_events > MAX_EVENTS {
+        return Err(ReadError::TooLong { events: n_events });
+    }
+    // Bounded by `MAX_EVENTS`
+    #[expect(clippy::cast_possible_truncation)]
+    let mut song = Song::new(n_events as u32);
+    song.event_wait_ms = event_wait_ms;
+    #[expect(clippy::cast_possible_truncation)]
+    {
+        let loop_event = |tick: Option<u64>| tick.map(event_of).map(|ev| ev.min(n_events) as u32);
+        // The repeat start has to point at an event for the song to be valid
+        song.repeat_range = loop_event(smf.loop_start).map_or(0, |ev| ev.min(n_events as u32 - 1))
+            ..loop_event(smf.loop_end).unwrap_or(song.repeat_range.end);
+    }
+
+    let mut ignored_messages = smf.ignored_messages;
+    let conv = Conversion {
+        smf: &smf,
+        event_of: &event_of,
+        // Length of a note in voice samples, rounded to nearest
+        voice_samples: &|ticks| {
+            let den = u128::from(ticks_per_quarter) * 1_000_000;
+            let samples = (u128::from(ticks) * u128::from(us_per_quarter) * u128::from(VOICE_RATE)
+                + den / 2)
+                / den;
+            u64::try_from(samples).unwrap_or(u64::MAX)
+        },
+        ticks_per_event,
+    };
+    let channels = melodic_channels(&smf.notes, &mut losses);
+    for (idx, (&channel, track)) in (0..).zip(channels.iter().zip(&mut song.melody_tracks)) {
+        conv.melody_track(
+            track,
+            TrackId::Melody(idx),
+            channel,
+            &mut losses,
+            &mut ignored_messages,
+        );
+    }
+    conv.percussion_track(
+        &mut song.percussion_track,
+        &mut losses,
+        &mut ignored_messages,
+    );
+    if ignored_messages > 0 {
+        losses.push(Loss::MessagesIgnored {
+            messages: ignored_messages,
+        });
+    }
+    Ok((song, ConversionReport { losses }))
+}
+
+/// The (at most 3) melodic channels with the most notes, in ascending order
+fn melodic_channels(notes: &[Note], losses: &mut Vec<Loss>) -> Vec<u8> {
+    let mut notes_per_channel = [0; 16];
+    for note in notes {
+        notes_per_channel[usize::from(note.channel)] += 1;
+    }
+    let mut channels: Vec<u8> = (0..16)
+        .filter(|&ch| ch != PERCUSSION_CHANNEL && notes_per_channel[usize::from(ch)] > 0)
+        .collect();
+    // Keep the channels with the most notes
+    channels.sort_by_key(|&ch| core::cmp::Reverse(notes_per_channel[usize::from(ch)]));
+    for &channel in channels.iter().skip(3) {
+        losses.push(Loss::ChannelDropped {
+            channel,
+            notes: notes_per_channel[usize::from(channel)],
+        });
+    }
+    channels.truncate(3);
+    channels.sort_unstable();
+    channels
+}
+
+/// Shared state for converting the notes of a MIDI file into tracks
+struct Conversion<'a> {
+    smf: &'a Smf,
+    /// The index of the event nearest to a tick
+    event_of: &'a dyn Fn(u64) -> u64,
+    /// Convert a duration in ticks into voice samples
+    voice_samples: &'a dyn Fn(u64) -> u64,
+    ticks_per_event: u64,
+}
+
+impl Conversion<'_> {
+    /// Fill `track` with the notes and controllers of `channel`
+    fn melody_track(
+        &self,
+        track: &mut MelodyTrack,
+        track_id: TrackId,
+        channel: u8,
+        losses: &mut Vec<Loss>,
+        ignored_messages: &mut usize,
+    ) {
+        let notes: Vec<&Note> = self
+            .smf
+            .notes
+            .iter()
+            .filter(|n| n.channel == channel)
+            .collect();
+        let keys: Vec<u8> = notes.iter().map(|n| n.key).collect();
+        let octave = best_octave(&keys);
+        track.octave = octave;
+        let mut folded = 0;
+        let keys = notes.iter().map(|&note| {
+            let (key, moved) = fit_key(note.key, octave);
+            folded += usize::from(moved);
+            (note, key)
+        });
+        let merged = self.place_notes(&mut track.base.events, keys);
+        if folded > 0 {
+            losses.push(Loss::NotesFolded {
+                track: track_id,
+                notes: folded,
+            });
+        }
+        if merged > 0 {
+            losses.push(Loss::NotesMerged {
+                track: track_id,
+                notes: merged,
+            });
+        }
+        let (len, differing) = self.note_length(&notes);
+        track.len = len;
+        if differing > 0 {
+            losses.push(Loss::LengthsUnified {
+                track: track_id,
+                notes: differing,
+            });
+        }
+        if notes.iter().any(|n| n.velocity != notes[0].velocity) {
+            losses.push(Loss::VelocitiesIgnored {
+                track: track_id,
+                notes: notes.len(),
+            });
+        }
+        track.base.vol = self.volume(channel, ignored_messages);
+        self.apply_pans(&mut track.base.events, channel, track_id, losses);
+    }
+    /// Fill `track` with the notes and controllers of the percussion channel
+    fn percussion_track(
+        &self,
+        track: &mut PercussionTrack,
+        losses: &mut Vec<Loss>,
+        ignored_messages: &mut usize,
+    ) {
+        let mut substituted = BTreeMap::new();
+        let mut dropped = BTreeMap::new();
+        let drum_notes = self
+            .smf
+            .notes
+            .iter()
+            .filter(|n| n.channel == PERCUSSION_CHANNEL);
+        let keys = drum_notes.filter_map(|note| {
+            let Some((key, exact)) = drum_key(note.key) else {
+                *dropped.entry(note.key).or_insert(0) += 1;
+                return None;
+            };
+            if !exact {
+                *substituted.entry(note.key).or_insert(0) += 1;
+            }
+            // Odd keys play the same drum more quietly
+            Some((note, key + PianoKey::from(note.velocity < QUIET_VELOCITY)))
+        });
+        let merged = self.place_notes(&mut track.base.events, keys);
+        if merged > 0 {
+            losses.push(Loss::NotesMerged {
+                track: TrackId::Percussion,
+                notes: merged,
+            });
+        }
+        losses.extend(
+            substituted
+                .into_iter()
+                .map(|(note, notes)| Loss::DrumsSubstituted { note, notes }),
+        );
+        losses.extend(
+            dropped
+                .into_iter()
+                .map(|(note, notes)| Loss::DrumsDropped { note, notes }),
+        );
+        track.base.vol = self.volume(PERCUSSION_CHANNEL, ignored_messages);
+        self.apply_pans(
+            &mut track.base.events,
+            PERCUSSION_CHANNEL,
+            TrackId::Percussion,
+            losses,
+        );
+    }
+    /// Press the key of each note at its event. Returns how many notes were merged.
+    fn place_notes<'n>(
+        &self,
+        events: &mut [Event],
+        notes: impl Iterator<Item = (&'n Note, PianoKey)>,
+    ) -> usize {
+        let mut merged = 0;
+        for (note, key) in notes {
+            // Notes start before the end of the song
+            #[expect(clippy::cast_possible_truncation)]
+            let event = &mut events[(self.event_of)(note.start) as usize];
+            merged += usize::from(press(event, key));
+        }
+        merged
+    }
+    /// The median length of `notes` in voice samples, and how many of them differ from it by
+    /// more than half an event
+    fn note_length(&self, notes: &[&Note]) -> (u16, usize) {
+        let lengths = notes
+            .iter()
+            .map(|n| (self.voice_samples)(n.end - n.start))
+            .collect();
+        median_length(lengths, (self.voice_samples)(self.ticks_per_event) / 2)
+    }
+    /// Track volume from the first volume controller of `channel`
+    ///
+    /// Later changes can't be represented, and are counted in `ignored`.
+    fn volume(&self, channel: u8, ignored: &mut usize) -> u16 {
+        let mut values = self
+            .smf
+            .controllers
+            .iter()
+            .filter(|c| c.channel == channel && c.controller == CC_VOLUME)
+            .map(|c| c.value);
+        let first = values.next();
+        *ignored += values.filter(|&v| Some(v) != first).count();
+        let gain = f64::from(first.unwrap_or(DEFAULT_CHANNEL_VOLUME)) / 127.0;
+        // The inverse of the volume curve of the player, clamped to its usual range
+        #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        {
+            math::round(math::fma(250.0, math::log10(gain), 300.0)).clamp(0.0, 300.0) as u16
+        }
+    }
+    /// Set the pan of events from the pan controllers of `channel`
+    fn apply_pans(
+        &self,
+        events: &mut [Event],
+        channel: u8,
+        track: TrackId,
+        losses: &mut Vec<Loss>,
+    ) {
+        let mut controllers: Vec<&ControlChange> = self
+            .smf
+            .controllers
+            .iter()
+            .filter(|c| c.channel == channel && c.controller == CC_PAN)
+            .collect();
+        controllers.sort_by_key(|c| c.tick);
+        let mut approximated = 0;
+        // The pan in effect before the event of the last pan change
+        let mut prev_pan = Pan::Center;
+        let mut last_event = None;
+        for ctl in controllers {
+            // Rounded to the nearest of the 7 positions
+            let pan = Pan::ALL[(usize::from(ctl.value) * 6 + 63) / 127];
+            // Pan changes after the last event are inaudible
+            let Ok(idx) = usize::try_from((self.event_of)(ctl.tick)) else {
+                continue;
+            };
+            let Some(event) = events.get_mut(idx) else {
+                continue;
+            };
+            // Centering at the start restates the initial pan, like the one `to_bytes` adds
+            if last_event.is_none() && ctl.tick == 0 && ctl.value == pan_cc(Pan::Center) {
+                continue;
+            }
+            if last_event == Some(idx) {
+                // Overriding a pan change that had an audible effect loses it
+                if event.pan_pos() != Some(prev_pan) {
+                    approximated += 1;
+                }
+            } else if let Some(last) = last_event {
+                prev_pan = events[last].pan_pos().unwrap_or(prev_pan);
+            }
+            if pan_cc(pan) != ctl.value {
+                approximated += 1;
+            }
+            events[idx].set_pan(pan);
+            last_event = Some(idx);
+        }
+        if approximated > 0 {
+            losses.push(Loss::PanApproximated {
+                track,
+                changes: approximated,
+            });
+        }
+    }
+}
+
+/// The percussion key of a General MIDI percussion note, and whether it's the drum that
+/// `to_bytes` maps to that note (rather than a similar drum)
+fn drum_key(note: u8) -> Option<(PianoKey, bool)> {
+    // Even keys, as odd keys play the same drum more quietly
+    if let Some(key) = (0..N_KEYS)
+        .step_by(2)
+        .find(|&k| DRUM_NOTES[usize::from(k)] == note)
+    {
+        return Some((key, true));
+    }
+    let similar = match note {
+        // Side stick, hand clap, electric snare
+        37 | 39 | 40 => DRUM_NOTES.iter().position(|&n| n == 38),
+        // Pedal hi-hat
+        44 => DRUM_NOTES.iter().position(|&n| n == 42),
+        // Other cymbals
+        51..=53 | 55 | 57 | 59 => DRUM_NOTES.iter().position(|&n| n == 49),
+        _ => None,
+    };
+    // Indices of `DRUM_NOTES` are piano keys
+    #[expect(clippy::cast_possible_truncation)]
+    similar.map(|key| (key as PianoKey, false))
+}
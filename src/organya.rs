@@ -0,0 +1,149 @@
+//! Conversion between songs and Organya (`.org`) files, the music format of Cave Story
+//!
+//! Organya is the successor of the format played by this crate, and shares its basic design: a
+//! fixed event grid looping between two points, and melody tracks with the same pitch range and
+//! volume scale.
+
+#[cfg(feature = "std")]
+pub use self::export::write;
+pub use self::export::{WriteError, to_bytes};
+pub use self::import::{ConversionReport, Loss, ReadError, read};
+
+use {
+    crate::{Pan, PianoKey, math},
+    alloc::{boxed::Box, vec::Vec},
+    core::ops::Range,
+};
+
+mod export;
+mod import;
+
+/// Number of melody tracks, which are followed by the same number of drum tracks
+const N_MELODY_TRACKS: usize = 8;
+/// Organya volumes are this much below track volumes on the same decibel scale
+const VOLUME_OFFSET: u16 = 45;
+/// Organya pan position of the center
+const PAN_CENTER: u8 = 6;
+/// Fine tuning value that leaves the pitch unchanged
+const DEFAULT_FINE_TUNE: u16 = 1000;
+/// Key, volume and pan values of this mean "unchanged"
+const UNCHANGED: u8 = 255;
+
+/// The percussion key playing the closest match of each Organya drum, if there is one
+///
+/// Only the even (louder) keys are used.
+const DRUM_KEYS: [Option<PianoKey>; 12] = [
+    // Bass01, Bass02
+    Some(0),
+    Some(2),
+    // Snare01, Snare02
+    Some(4),
+    Some(4),
+    // Tom01
+    None,
+    // HiClose, HiOpen
+    Some(8),
+    Some(10),
+    // Crash
+    Some(12),
+    // Per01, Per02
+    None,
+    None,
+    // Bass03
+    Some(0),
+    // Tom02
+    None,
+];
+
+/// The 100 melody waveforms of Organya, which aren't included with this crate
+///
+/// Each waveform is a single cycle of 256 signed samples, like [`MelodyTrack::waveform`].
+///
+/// [`MelodyTrack::waveform`]: crate::MelodyTrack::waveform
+pub type WaveBank = [[i8; 256]; 100];
+
+/// Read a wave bank from `data`, 100 waveforms of 256 signed 8-bit samples in a row
+///
+/// This is the layout of the `WAVE100` resource of Organya. Returns `None` if `data` is shorter
+/// than 25600 bytes, and ignores anything after that.
+#[must_use]
+pub fn wave_bank_from_bytes(data: &[u8]) -> Option<Box<WaveBank>> {
+    let data = data.get(..100 * 256)?;
+    let waves: Vec<[i8; 256]> = data
+        .chunks_exact(256)
+        .map(|chunk| core::array::from_fn(|i| chunk[i].cast_signed()))
+        .collect();
+    waves.into_boxed_slice().try_into().ok()
+}
+
+/// Options for converting a song into an Organya file
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteOptions<'a> {
+    /// The Organya instrument (0 to 99) playing each melody track
+    ///
+    /// Organya can only play its own waveforms. Melody tracks without an instrument here play
+    /// the [`closest_instrument`] of `wave_bank`, or instrument 0 without one.
+    pub instruments: [Option<u8>; 3],
+    /// The Organya waveforms, to pick the instruments that weren't chosen by hand
+    pub wave_bank: Option<&'a WaveBank>,
+}
+
+/// The Organya instrument of `bank` sounding closest to `waveform`
+///
+/// Waveforms are compared by their shape, regardless of their volume.
+#[must_use]
+pub fn closest_instrument(waveform: &[i8; 256], bank: &WaveBank) -> u8 {
+    let energy =
+        |wave: &[i8; 256]| -> f64 { wave.iter().map(|&s| math::powi(f64::from(s), 2)).sum() };
+    let similarity = |wave: &[i8; 256]| {
+        let dot: f64 = waveform
+            .iter()
+            .zip(wave)
+            .map(|(&a, &b)| f64::from(a) * f64::from(b))
+            .sum();
+        dot / math::sqrt(energy(waveform) * energy(wave)).max(1.0)
+    };
+    (0..)
+        .zip(bank)
+        .max_by(|(_, a), (_, b)| similarity(a).total_cmp(&similarity(b)))
+        .map_or(0, |(idx, _)| idx)
+}
+
+/// The pan position closest to the Organya pan position `pan` (0 to 12)
+fn pan_from_organya(pan: u8) -> Pan {
+    let offset = organya_pan_offset(pan);
+    Pan::ALL
+        .into_iter()
+        .min_by_key(|p| p.volume_offset().abs_diff(offset))
+        .unwrap_or(Pan::Center)
+}
+
+/// The volume offset (see [`Pan::volume_offset`]) of the Organya pan position `pan`
+const fn organya_pan_offset(pan: u8) -> i16 {
+    const PAN_TABLE: [i16; 13] = [0, 43, 86, 129, 172, 215, 256, 297, 340, 383, 426, 469, 512];
+    let idx = if pan < 13 { pan } else { PAN_CENTER };
+    (256 - PAN_TABLE[idx as usize]) * 10
+}
+
+struct Org {
+    wait_ms: u16,
+    repeat_range: Range<u32>,
+    tracks: [OrgTrack; 16],
+}
+
+#[derive(Default)]
+struct OrgTrack {
+    fine_tune: u16,
+    instrument: u8,
+    pizzicato: bool,
+    notes: Vec<OrgNote>,
+}
+
+/// A note, or a volume/pan change if `key` is [`UNCHANGED`]
+struct OrgNote {
+    x: u32,
+    key: u8,
+    len: u8,
+    vol: u8,
+    pan: u8,
+}
@@ -0,0 +1,307 @@
+use {
+    super::{
+        ConversionReport, DEFAULT_FINE_TUNE, Loss, N_MELODY_TRACKS, Org, OrgNote, OrgTrack,
+        PAN_CENTER, UNCHANGED, VOLUME_OFFSET, WriteOptions, closest_instrument, organya_pan_offset,
+    },
+    crate::{Event, MelodyTrack, N_KEYS, Pan, PianoKey, Song, TrackId, convert::VOICE_RATE},
+    alloc::vec::Vec,
+};
+
+/// Highest key of an Organya track
+const MAX_KEY: u8 = 95;
+/// Drum key playing the drum samples at about their original rate (drums play at
+/// `key * 800 + 100` Hz)
+const DRUM_KEY: u8 = 27;
+
+/// The Organya drum playing the closest match of each percussion key
+///
+/// This follows the samples of [`DRUM_SAMPLES`](crate::DRUM_SAMPLES): bass drum 1, bass drum 2,
+/// snare, closed hi-hat, open hi-hat, and crash cymbal for the rest.
+const DRUM_INSTRUMENTS: [u8; N_KEYS as usize] = [
+    0, 0, 1, 1, 2, 2, 2, 2, 5, 5, 6, 6, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+];
+
+/// Convert `song` into an Organya file
+///
+/// Each event becomes one click, and the repeat range becomes the loop points.
+///
+/// Organya tracks play one note at a time, so the melody tracks are spread over the 8 Organya
+/// melody tracks, with each note of a chord on its own track. If a song has more simultaneous
+/// notes than that, the lowest notes of the largest chords are left out. The percussion keys are
+/// played by the closest Organya drums, with the quieter odd keys at a lower volume.
+///
+/// Organya has no way to include the waveforms and envelopes of a song, so the melody tracks
+/// play the instruments of `opts`, or the Organya instruments closest to their waveforms.
+/// Everything that couldn't be converted exactly is listed in the returned report.
+///
+/// # Errors
+///
+/// - If a track has more than 65535 notes and pan changes
+pub fn to_bytes(
+    song: &Song,
+    opts: &WriteOptions,
+) -> Result<(Vec<u8>, ConversionReport), WriteError> {
+    let mut report = ConversionReport::default();
+    let wait_ms = u16::try_from(song.event_wait_ms.max(1)).unwrap_or(u16::MAX);
+    let mut tracks: [OrgTrack; 16] = core::array::from_fn(|_| OrgTrack {
+        fine_tune: DEFAULT_FINE_TUNE,
+        ..OrgTrack::default()
+    });
+    let mut org_tracks = tracks[..N_MELODY_TRACKS].iter_mut();
+    // Length of a click in voice samples
+    let click_samples = (u64::from(wait_ms) * VOICE_RATE / 1000).max(1);
+    for (((track, voices), instrument), idx) in song
+        .melody_tracks
+        .iter()
+        .zip(melody_voices(song))
+        .zip(opts.instruments)
+        .zip(0..)
+    {
+        if voices == 0 {
+            continue;
+        }
+        let id = TrackId::Melody(idx);
+        let instrument = melody_instrument(track, id, instrument, opts, &mut report);
+        if voices > 1 {
+            report.losses.push(Loss::ChordsSplit {
+                track: id,
+                tracks: voices,
+            });
+        }
+        let dropped: usize = (track.base.events.iter())
+            .map(|event| event.keys_down().count().saturating_sub(voices))
+            .sum();
+        if dropped > 0 {
+            report.losses.push(Loss::NotesDropped {
+                track: id,
+                notes: dropped,
+            });
+        }
+        let len = (u64::from(track.len) + click_samples / 2) / click_samples;
+        let len = u8::try_from(len.max(1)).unwrap_or(u8::MAX);
+        let vol = organya_volume(track.base.vol);
+        for (voice, org_track) in (0..voices).zip(&mut org_tracks) {
+            org_track.instrument = instrument;
+            org_track.notes = voice_notes(&track.base.events, |event| {
+                // Higher notes are more likely to carry the melody, so they are kept first
+                let keys: Vec<PianoKey> = event.keys_down().collect();
+                let key = *keys.iter().rev().nth(voice)?;
+                Some((organya_key(key, track.octave), len, vol))
+            });
+        }
+    }
+    let drums = &song.percussion_track;
+    let vol = organya_volume(drums.base.vol);
+    // Every second key is played quieter, at 70% of the track volume (which still fits a u16)
+    #[expect(clippy::cast_possible_truncation)]
+    let low_vol = organya_volume((u32::from(drums.base.vol) * 7 / 10) as u16);
+    let mut instruments = DRUM_INSTRUMENTS.to_vec();
+    instruments.dedup();
+    for (instrument, org_track) in instruments.into_iter().zip(&mut tracks[N_MELODY_TRACKS..]) {
+        org_track.instrument = instrument;
+        let notes = voice_notes(&drums.base.events, |event| {
+            let vol = event
+                .keys_down()
+                .filter(|&key| DRUM_INSTRUMENTS[usize::from(key)] == instrument)
+                .map(|key| if key.is_multiple_of(2) { vol } else { low_vol })
+                .max()?;
+            Some((DRUM_KEY, 1, vol))
+        });
+        // Drums that are never played don't need the pan changes
+        if notes.iter().any(|note| note.key != UNCHANGED) {
+            org_track.notes = notes;
+        }
+    }
+    let range = &song.repeat_range;
+    let org = Org {
+        wait_ms,
+        repeat_range: range.start..range.end.max(range.start.saturating_add(1)),
+        tracks,
+    };
+    Ok((org.to_bytes()?, report))
+}
+
+/// Convert `song` into an Organya file like [`to_bytes`], and write it to `writer`
+///
+/// # Errors
+///
+/// - If writing to `writer` fails
+/// - If a track has more than 65535 notes and pan changes
+#[cfg(feature = "std")]
+pub fn write<W: std::io::Write>(
+    song: &Song,
+    opts: &WriteOptions,
+    mut writer: W,
+) -> std::io::Result<ConversionReport> {
+    let (bytes, report) = to_bytes(song, opts).map_err(std::io::Error::other)?;
+    writer.write_all(&bytes)?;
+    Ok(report)
+}
+
+/// Error that can happen when converting a song into an Organya file
+#[derive(Debug)]
+pub enum WriteError {
+    /// An Organya track would have more than 65535 notes and pan changes
+    TooManyNotes {
+        /// Index of the Organya track
+        track: usize,
+    },
+}
+
+impl core::fmt::Display for WriteError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            WriteError::TooManyNotes { track } => {
+                write!(f, "Organya track {track} has too many notes")
+            }
+        }
+    }
+}
+
+impl core::error::Error for WriteError {}
+
+/// The Organya instrument playing `track`, which is `instrument` if one was chosen
+///
+/// Otherwise this is the closest instrument of the wave bank of `opts`, or instrument 0.
+fn melody_instrument(
+    track: &MelodyTrack,
+    id: TrackId,
+    instrument: Option<u8>,
+    opts: &WriteOptions,
+    report: &mut ConversionReport,
+) -> u8 {
+    if let Some(instrument) = instrument {
+        return instrument;
+    }
+    let Some(bank) = opts.wave_bank else {
+        report.losses.push(Loss::InstrumentDefaulted { track: id });
+        return 0;
+    };
+    let instrument = closest_instrument(&track.waveform, bank);
+    if bank[usize::from(instrument)] != track.waveform {
+        report.losses.push(Loss::InstrumentApproximated {
+            track: id,
+            instrument,
+        });
+    }
+    instrument
+}
+
+/// How many Organya tracks each melody track is spread over
+///
+/// Each melody track gets one track for each note of its largest chord, as long as there are
+/// tracks left. Tracks are handed out to the melody tracks in turn.
+fn melody_voices(song: &Song) -> [usize; 3] {
+    let polyphony = song.melody_tracks.each_ref().map(|track| {
+        track
+            .base
+            .events
+            .iter()
+            .map(|event| event.keys_down().count())
+            .max()
+            .unwrap_or(0)
+    });
+    let mut voices = [0; 3];
+    let mut free = N_MELODY_TRACKS;
+    while free > 0 {
+        let mut assigned = false;
+        for (voices, polyphony) in voices.iter_mut().zip(polyphony) {
+            if *voices < polyphony && free > 0 {
+                *voices += 1;
+                free -= 1;
+                assigned = true;
+            }
+        }
+        if !assigned {
+            break;
+        }
+    }
+    voices
+}
+
+/// The notes and pan changes of an Organya track playing `events`
+///
+/// `note` returns the key, length and volume of the note the track plays in an event, if any.
+fn voice_notes(events: &[Event], note: impl Fn(Event) -> Option<(u8, u8, u8)>) -> Vec<OrgNote> {
+    let mut notes: Vec<OrgNote> = Vec::new();
+    for (x, &event) in (0..).zip(events) {
+        let pan = event.pan_pos().map(organya_pan);
+        let (key, len, vol) = note(event).unwrap_or((UNCHANGED, UNCHANGED, UNCHANGED));
+        if key == UNCHANGED && pan.is_none() {
+            continue;
+        }
+        // Tracks start out centered
+        let pan = pan.unwrap_or(if notes.is_empty() {
+            PAN_CENTER
+        } else {
+            UNCHANGED
+        });
+        notes.push(OrgNote {
+            x,
+            key,
+            len,
+            vol,
+            pan,
+        });
+    }
+    notes
+}
+
+/// The Organya key of `key` played by a melody track at `octave`
+///
+/// Keys above the Organya range are moved down by octaves.
+fn organya_key(key: PianoKey, octave: u8) -> u8 {
+    let mut key = 12 * u16::from(octave) + u16::from(key);
+    while key > u16::from(MAX_KEY) {
+        key -= 12;
+    }
+    // Reduced to the Organya range above
+    #[expect(clippy::cast_possible_truncation)]
+    {
+        key as u8
+    }
+}
+
+/// The Organya volume of a track volume of `vol`
+fn organya_volume(vol: u16) -> u8 {
+    u8::try_from(vol.saturating_sub(VOLUME_OFFSET))
+        .unwrap_or(u8::MAX)
+        .min(UNCHANGED - 1)
+}
+
+/// The Organya pan position closest to `pan`
+fn organya_pan(pan: Pan) -> u8 {
+    (0..=12)
+        .min_by_key(|&p| organya_pan_offset(p).abs_diff(pan.volume_offset()))
+        .unwrap_or(PAN_CENTER)
+}
+
+impl Org {
+    fn to_bytes(&self) -> Result<Vec<u8>, WriteError> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"Org-02");
+        out.extend_from_slice(&self.wait_ms.to_le_bytes());
+        // 4 beats per measure and 4 clicks per beat, the same layout as the MIDI export
+        out.extend_from_slice(&[4, 4]);
+        out.extend_from_slice(&self.repeat_range.start.to_le_bytes());
+        out.extend_from_slice(&self.repeat_range.end.to_le_bytes());
+        for (track, idx) in self.tracks.iter().zip(0..) {
+            let count = u16::try_from(track.notes.len())
+                .map_err(|_| WriteError::TooManyNotes { track: idx })?;
+            out.extend_from_slice(&track.fine_tune.to_le_bytes());
+            out.push(track.instrument);
+            out.push(u8::from(track.pizzicato));
+            out.extend_from_slice(&count.to_le_bytes());
+        }
+        for track in &self.tracks {
+            for note in &track.notes {
+                out.extend_from_slice(&note.x.to_le_bytes());
+            }
+            out.extend(track.notes.iter().map(|n| n.key));
+            out.extend(track.notes.iter().map(|n| n.len));
+            out.extend(track.notes.iter().map(|n| n.vol));
+            out.extend(track.notes.iter().map(|n| n.pan));
+        }
+        Ok(out)
+    }
+}
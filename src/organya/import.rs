@@ -0,0 +1,583 @@
+use {
+    super::{
+        DEFAULT_FINE_TUNE, DRUM_KEYS, N_MELODY_TRACKS, Org, OrgNote, OrgTrack, PAN_CENTER,
+        UNCHANGED, VOLUME_OFFSET, organya_pan_offset, pan_from_organya,
+    },
+    crate::{
+        Event, MelodyTrack, PianoKey, Song, TrackId,
+        convert::{
+            MAX_EVENTS, OCTAVE_0_NOTE, VOICE_RATE, best_octave, fit_key, median_length, press,
+        },
+        read_cursor::ReadCursor,
+    },
+    alloc::{boxed::Box, collections::BTreeMap, vec::Vec},
+};
+
+/// Volume of notes played before any volume is set
+const DEFAULT_VOLUME: u8 = 200;
+
+/// Read an Organya file, and convert it into a song
+///
+/// Every Organya click becomes one event, so the tempo and repeat range carry over exactly.
+/// The three melody tracks with the most notes are kept, and the other melody tracks are
+/// merged into the kept track closest in pitch, with the octave of each track chosen to fit
+/// the most notes. All drum tracks are merged into the percussion track, using the closest
+/// percussion key.
+///
+/// Organya instruments are not available, so the melody tracks play a simple default
+/// instrument (see [`Song::new`]). Everything else that couldn't be converted exactly is
+/// listed in the returned report.
+///
+/// # Errors
+///
+/// - If the file doesn't start with an Organya magic marker (`Org-01` to `Org-03`)
+/// - If the file is too short
+/// - If the song would be longer than a million events
+pub fn read(data: &[u8]) -> Result<(Song, ConversionReport), ReadError> {
+    convert(&parse(data)?)
+}
+
+/// The conversion losses of [`read`], or of [`to_bytes`](super::to_bytes)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConversionReport {
+    /// What couldn't be converted exactly, in the order it was encountered
+    pub losses: Vec<Loss>,
+}
+
+impl ConversionReport {
+    /// Whether the song plays like the Organya file, apart from the instruments of an import
+    #[must_use]
+    pub const fn is_lossless(&self) -> bool {
+        self.losses.is_empty()
+    }
+}
+
+/// Something that couldn't be converted exactly when importing or exporting an Organya file
+///
+/// Organya tracks are numbered from 0, with the drum tracks at 8 to 15.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Loss {
+    /// An Organya melody track was merged into a melody track that also plays other notes
+    TrackMerged {
+        /// The Organya track
+        from: u8,
+        /// The melody track it was merged into
+        into: TrackId,
+    },
+    /// The instrument of an Organya melody track was replaced by the default instrument
+    InstrumentReplaced {
+        /// The Organya track
+        track: u8,
+        /// The Organya instrument number
+        instrument: u8,
+    },
+    /// The fine tuning of an Organya track was ignored
+    FineTuneIgnored {
+        /// The Organya track
+        track: u8,
+    },
+    /// An Organya track plays each waveform only once ("pizzicato"), which was ignored
+    PizzicatoIgnored {
+        /// The Organya track
+        track: u8,
+    },
+    /// Notes outside of the two octaves a track can play were moved by whole octaves to fit
+    NotesFolded {
+        /// The track the notes were moved on
+        track: TrackId,
+        /// How many notes were moved
+        notes: usize,
+    },
+    /// Notes were merged with another note of the same key in the same event
+    NotesMerged {
+        /// The track the notes were merged on
+        track: TrackId,
+        /// How many notes were lost by merging
+        notes: usize,
+    },
+    /// Notes play for the note length of the track, which differs from their own length
+    LengthsUnified {
+        /// The track with the notes
+        track: TrackId,
+        /// How many notes differ from the note length by more than half an event
+        notes: usize,
+    },
+    /// Notes or volume changes differ from the track volume, which all notes play at
+    VolumesIgnored {
+        /// The track with the notes
+        track: TrackId,
+        /// How many notes and volume changes were affected
+        notes: usize,
+    },
+    /// Pan changes were moved to the closest pan position, or were overridden by another pan
+    /// change within the same event
+    PanApproximated {
+        /// The track with the pan changes
+        track: TrackId,
+        /// How many pan changes were affected
+        changes: usize,
+    },
+    /// Notes of an Organya drum without a similar percussion key were dropped
+    DrumsDropped {
+        /// The Organya drum number
+        instrument: u8,
+        /// How many notes were dropped
+        notes: usize,
+    },
+    /// A melody track plays the Organya instrument closest to its waveform, which differs from
+    /// the waveform
+    InstrumentApproximated {
+        /// The melody track
+        track: TrackId,
+        /// The Organya instrument number
+        instrument: u8,
+    },
+    /// A melody track plays Organya instrument 0, as no instrument or wave bank was given
+    InstrumentDefaulted {
+        /// The melody track
+        track: TrackId,
+    },
+    /// The chords of a melody track were split over several Organya tracks, which can't share
+    /// a pan or tell which notes belong together
+    ChordsSplit {
+        /// The melody track
+        track: TrackId,
+        /// How many Organya tracks it was split over
+        tracks: usize,
+    },
+    /// The lowest notes of chords were dropped, as there were no Organya tracks left for them
+    NotesDropped {
+        /// The melody track
+        track: TrackId,
+        /// How many notes were dropped
+        notes: usize,
+    },
+}
+
+impl core::fmt::Display for Loss {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Loss::TrackMerged { from, into } => {
+                write!(f, "Organya track {from} merged into {into}")
+            }
+            Loss::InstrumentReplaced { track, instrument } => write!(
+                f,
+                "Instrument {instrument} of Organya track {track} replaced by the default instrument"
+            ),
+            Loss::FineTuneIgnored { track } => {
+                write!(f, "Fine tuning of Organya track {track} ignored")
+            }
+            Loss::PizzicatoIgnored { track } => {
+                write!(f, "Pizzicato of Organya track {track} ignored")
+            }
+            Loss::NotesFolded { track, notes } => {
+                write!(f, "{notes} notes moved by octaves to fit {track}")
+            }
+            Loss::NotesMerged { track, notes } => {
+                write!(f, "{notes} notes merged with another note on {track}")
+            }
+            Loss::LengthsUnified { track, notes } => {
+                write!(f, "{notes} notes changed to the note length of {track}")
+            }
+            Loss::VolumesIgnored { track, notes } => {
+                write!(f, "Volumes of {notes} notes ignored on {track}")
+            }
+            Loss::PanApproximated { track, changes } => {
+                write!(f, "{changes} pan changes approximated on {track}")
+            }
+            Loss::DrumsDropped { instrument, notes } => {
+                write!(
+                    f,
+                    "{notes} notes of drum {instrument} dropped (no similar drum)"
+                )
+            }
+            Loss::InstrumentApproximated { track, instrument } => {
+                write!(
+                    f,
+                    "Waveform of {track} approximated by Organya instrument {instrument}"
+                )
+            }
+            Loss::InstrumentDefaulted { track } => {
+                write!(
+                    f,
+                    "Organya instrument 0 used for {track} (no instrument chosen)"
+                )
+            }
+            Loss::ChordsSplit { track, tracks } => {
+                write!(f, "Chords of {track} split over {tracks} Organya tracks")
+            }
+            Loss::NotesDropped { track, notes } => {
+                write!(
+                    f,
+                    "{notes} chord notes of {track} dropped (no Organya tracks left)"
+                )
+            }
+        }
+    }
+}
+
+/// Error that can happen when reading an Organya file
+#[derive(Debug)]
+pub enum ReadError {
+    /// Invalid magic (not `Org-01`, `Org-02` or `Org-03`)
+    InvalidMagic,
+    /// End of file was reached prematurely
+    PrematureEof {
+        /// Byte offset of the value that couldn't be read
+        offset: usize,
+    },
+    /// The song would have more events than supported
+    TooLong {
+        /// How many events the song would have
+        events: u64,
+    },
+}
+
+impl core::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ReadError::InvalidMagic => f.write_str("Invalid magic (expected Org-01 to Org-03)"),
+            ReadError::PrematureEof { offset } => {
+                write!(f, "End of file reached prematurely at offset {offset:#X}")
+            }
+            ReadError::TooLong { events } => write!(f, "Song is too long ({events} events)"),
+        }
+    }
+}
+
+impl core::error::Error for ReadError {}
+
+/// Reads a value with `read`, reporting premature end of file as an error
+fn next<'a, T>(
+    cur: &mut ReadCursor<'a>,
+    read: impl FnOnce(&mut ReadCursor<'a>) -> Option<T>,
+) -> Result<T, ReadError> {
+    let offset = cur.pos();
+    read(cur).ok_or(ReadError::PrematureEof { offset })
+}
+
+fn parse(data: &[u8]) -> Result<Org, ReadError> {
+    let mut cur = ReadCursor::new(data);
+    if !matches!(cur.next_bytes(), Some(b"Org-01" | b"Org-02" | b"Org-03")) {
+        return Err(ReadError::InvalidMagic);
+    }
+    let wait_ms = next(&mut cur, ReadCursor::next_u16_le)?;
+    // Beats per measure and clicks per beat only matter for display
+    next(&mut cur, ReadCursor::next_bytes::<2>)?;
+    let repeat_start = next(&mut cur, ReadCursor::next_u32_le)?;
+    let repeat_end = next(&mut cur, ReadCursor::next_u32_le)?;
+    let mut tracks: [OrgTrack; 16] = Default::default();
+    let mut note_counts = [0; 16];
+    for (track, count) in tracks.iter_mut().zip(&mut note_counts) {
+        track.fine_tune = next(&mut cur, ReadCursor::next_u16_le)?;
+        track.instrument = next(&mut cur, ReadCursor::next_u8)?;
+        track.pizzicato = next(&mut cur, ReadCursor::next_u8)? != 0;
+        *count = usize::from(next(&mut cur, ReadCursor::next_u16_le)?);
+    }
+    for (track, n) in tracks.iter_mut().zip(note_counts) {
+        let xs: Box<[[u8; 4]]> = next(&mut cur, |cur| cur.next_n(n))?;
+        let keys = next(&mut cur, |cur| cur.next_slice(n))?;
+        let lens = next(&mut cur, |cur| cur.next_slice(n))?;
+        let vols = next(&mut cur, |cur| cur.next_slice(n))?;
+        let pans = next(&mut cur, |cur| cur.next_slice(n))?;
+        track.notes = (0..n)
+            .map(|i| OrgNote {
+                x: u32::from_le_bytes(xs[i]),
+                key: keys[i],
+                len: lens[i],
+                vol: vols[i],
+                pan: pans[i],
+            })
+            .collect();
+    }
+    Ok(Org {
+        wait_ms,
+        repeat_range: repeat_start..repeat_end,
+        tracks,
+    })
+}
+
+/// A note to be played, with the volume and pan that are in effect
+#[derive(Clone, Copy)]
+struct Played {
+    x: u32,
+    /// MIDI note number
+    note: u8,
+    len: u8,
+    vol: u8,
+}
+
+/// The notes, pan changes, and number of volume changes during notes of an Organya track
+fn play(track: &OrgTrack) -> (Vec<Played>, Vec<(u32, u8)>, usize) {
+    let (mut vol, mut pan) = (DEFAULT_VOLUME, PAN_CENTER);
+    let (mut played, mut pans, mut vol_changes) = (Vec::new(), Vec::new(), 0);
+    for note in &track.notes {
+        if note.vol != UNCHANGED {
+            vol = note.vol;
+            vol_changes += usize::from(note.key == UNCHANGED);
+        }
+        if note.pan != UNCHANGED && note.pan != pan {
+            pan = note.pan;
+            pans.push((note.x, pan));
+        }
+        if note.key != UNCHANGED {
+            played.push(Played {
+                x: note.x,
+                note: OCTAVE_0_NOTE.saturating_add(note.key),
+                len: note.len,
+                vol,
+            });
+        }
+    }
+    (played, pans, vol_changes)
+}
+
+fn convert(org: &Org) -> Result<(Song, ConversionReport), ReadError> {
+    let mut losses = Vec::new();
+    let last_note = org
+        .tracks
+        .iter()
+        .flat_map(|track| &track.notes)
+        .map(|note| u64::from(note.x) + 1)
+        .max();
+    let n_events = u64::from(org.repeat_range.end)
+        .max(last_note.unwrap_or(0))
+        .max(1);
+    if n_events > MAX_EVENTS {
+        return Err(ReadError::TooLong { events: n_events });
+    }
+    // Bounded by `MAX_EVENTS`
+    #[expect(clippy::cast_possible_truncation)]
+    let n_events = n_events as u32;
+    let mut song = Song::new(n_events);
+    song.event_wait_ms = u32::from(org.wait_ms);
+    // The repeat start has to point at an event for the song to be valid
+    song.repeat_range =
+        org.repeat_range.start.min(n_events - 1)..org.repeat_range.end.min(n_events);
+    let groups = group_melody_tracks(&org.tracks[..N_MELODY_TRACKS], &mut losses);
+    for ((idx, group), track) in (0..).zip(groups).zip(&mut song.melody_tracks) {
+        melody_track(org, &group, track, TrackId::Melody(idx), &mut losses);
+    }
+    convert_drums(org, &mut song, &mut losses);
+    Ok((song, ConversionReport { losses }))
+}
+
+/// Fill `track` with the notes and pan changes of the Organya tracks `group`
+fn melody_track(
+    org: &Org,
+    group: &[u8],
+    track: &mut MelodyTrack,
+    track_id: TrackId,
+    losses: &mut Vec<Loss>,
+) {
+    let (mut played, mut pans, mut vol_changes) = (Vec::new(), Vec::new(), 0);
+    for &org_idx in group {
+        let org_track = &org.tracks[usize::from(org_idx)];
+        losses.push(Loss::InstrumentReplaced {
+            track: org_idx,
+            instrument: org_track.instrument,
+        });
+        if org_track.fine_tune != DEFAULT_FINE_TUNE {
+            losses.push(Loss::FineTuneIgnored { track: org_idx });
+        }
+        if org_track.pizzicato {
+            losses.push(Loss::PizzicatoIgnored { track: org_idx });
+        }
+        let (p, pan, vol) = play(org_track);
+        played.extend(p);
+        pans.extend(pan);
+        vol_changes += vol;
+    }
+    if played.is_empty() {
+        return;
+    }
+    let notes: Vec<u8> = played.iter().map(|p| p.note).collect();
+    track.octave = best_octave(&notes);
+    let (mut folded, mut merged) = (0, 0);
+    for p in &played {
+        let (key, moved) = fit_key(p.note, track.octave);
+        folded += usize::from(moved);
+        merged += usize::from(press(&mut track.base.events[p.x as usize], key));
+    }
+    if folded > 0 {
+        losses.push(Loss::NotesFolded {
+            track: track_id,
+            notes: folded,
+        });
+    }
+    if merged > 0 {
+        losses.push(Loss::NotesMerged {
+            track: track_id,
+            notes: merged,
+        });
+    }
+    // Length of a click in voice samples
+    let click_samples = u64::from(org.wait_ms) * VOICE_RATE / 1000;
+    let lengths = played
+        .iter()
+        .map(|p| u64::from(p.len) * click_samples)
+        .collect();
+    let (len, differing) = median_length(lengths, click_samples / 2);
+    track.len = len;
+    if differing > 0 {
+        losses.push(Loss::LengthsUnified {
+            track: track_id,
+            notes: differing,
+        });
+    }
+    let (vol, differing) = common_volume(&played);
+    track.base.vol = vol;
+    if differing + vol_changes > 0 {
+        losses.push(Loss::VolumesIgnored {
+            track: track_id,
+            notes: differing + vol_changes,
+        });
+    }
+    apply_pans(&mut track.base.events, pans, track_id, losses);
+}
+
+/// Assign the Organya melody tracks to the 3 melody tracks
+///
+/// The 3 tracks with the most notes are kept, and the others are merged into the kept track
+/// with the closest average pitch.
+fn group_melody_tracks(tracks: &[OrgTrack], losses: &mut Vec<Loss>) -> [Vec<u8>; 3] {
+    let note_count = |idx: u8| {
+        tracks[usize::from(idx)]
+            .notes
+            .iter()
+            .filter(|n| n.key != UNCHANGED)
+            .count()
+    };
+    let average_pitch = |idx: u8| {
+        let keys = tracks[usize::from(idx)]
+            .notes
+            .iter()
+            .filter(|n| n.key != UNCHANGED);
+        let sum: u64 = keys.map(|n| u64::from(n.key)).sum();
+        sum / note_count(idx).max(1) as u64
+    };
+    // Lossless conversion into 8 bit track indices, as there are 8 melody tracks
+    #[expect(clippy::cast_possible_truncation)]
+    let mut by_notes: Vec<u8> = (0..tracks.len() as u8)
+        .filter(|&idx| note_count(idx) > 0)
+        .collect();
+    by_notes.sort_by_key(|&idx| core::cmp::Reverse(note_count(idx)));
+    let mut kept: Vec<u8> = by_notes.iter().copied().take(3).collect();
+    kept.sort_unstable();
+    let mut groups: [Vec<u8>; 3] = Default::default();
+    for (group, &idx) in groups.iter_mut().zip(&kept) {
+        group.push(idx);
+    }
+    for &idx in by_notes.iter().skip(3) {
+        let pitch = average_pitch(idx);
+        let Some(target) = (0..kept.len()).min_by_key(|&k| average_pitch(kept[k]).abs_diff(pitch))
+        else {
+            continue;
+        };
+        groups[target].push(idx);
+        // At most 3 kept tracks
+        #[expect(clippy::cast_possible_truncation)]
+        losses.push(Loss::TrackMerged {
+            from: idx,
+            into: TrackId::Melody(target as u8),
+        });
+    }
+    groups
+}
+
+/// The most common volume of `played` as a track volume, and how many notes differ from it
+fn common_volume(played: &[Played]) -> (u16, usize) {
+    let mut counts = BTreeMap::new();
+    for p in played {
+        *counts.entry(p.vol).or_insert(0) += 1;
+    }
+    let vol = counts
+        .iter()
+        .max_by_key(|&(_, count)| count)
+        .map_or(DEFAULT_VOLUME, |(&vol, _)| vol);
+    let differing = played.iter().filter(|p| p.vol != vol).count();
+    (u16::from(vol) + VOLUME_OFFSET, differing)
+}
+
+/// Set the pan of events from the pan changes `pans` (click and Organya pan position)
+fn apply_pans(
+    events: &mut [Event],
+    mut pans: Vec<(u32, u8)>,
+    track: TrackId,
+    losses: &mut Vec<Loss>,
+) {
+    // Pan changes of merged tracks are interleaved
+    pans.sort_by_key(|&(x, _)| x);
+    let mut approximated = 0;
+    let mut last_x = None;
+    for (x, org_pan) in pans {
+        let pan = pan_from_organya(org_pan);
+        let event = &mut events[x as usize];
+        if last_x == Some(x) && event.pan_pos() != Some(pan) {
+            approximated += 1;
+        }
+        if pan.volume_offset() != organya_pan_offset(org_pan) {
+            approximated += 1;
+        }
+        event.set_pan(pan);
+        last_x = Some(x);
+    }
+    if approximated > 0 {
+        losses.push(Loss::PanApproximated {
+            track,
+            changes: approximated,
+        });
+    }
+}
+
+/// Merge the drum tracks into the percussion track of `song`
+fn convert_drums(org: &Org, song: &mut Song, losses: &mut Vec<Loss>) {
+    let track = &mut song.percussion_track;
+    let track_id = TrackId::Percussion;
+    let (mut played, mut pans, mut vol_changes) = (Vec::new(), Vec::new(), 0);
+    let mut dropped = BTreeMap::new();
+    for org_track in &org.tracks[N_MELODY_TRACKS..] {
+        let (p, pan, vol) = play(org_track);
+        let key = DRUM_KEYS
+            .get(usize::from(org_track.instrument))
+            .copied()
+            .flatten();
+        match key {
+            Some(key) => played.extend(p.into_iter().map(|p| (p, key))),
+            None if !p.is_empty() => {
+                *dropped.entry(org_track.instrument).or_insert(0) += p.len();
+            }
+            None => {}
+        }
+        pans.extend(pan);
+        vol_changes += vol;
+    }
+    let (vol, _) = common_volume(&played.iter().map(|(p, _)| *p).collect::<Vec<_>>());
+    track.base.vol = vol;
+    let mut merged = 0;
+    for (p, key) in &played {
+        // Odd keys play the same drum at 70% of the track volume, so use them for notes that are
+        // closer to that
+        let quiet = f64::from(u16::from(p.vol) + VOLUME_OFFSET) < 0.85 * f64::from(vol);
+        let key = key + PianoKey::from(quiet);
+        merged += usize::from(press(&mut track.base.events[p.x as usize], key));
+    }
+    if merged > 0 {
+        losses.push(Loss::NotesMerged {
+            track: track_id,
+            notes: merged,
+        });
+    }
+    if vol_changes > 0 {
+        losses.push(Loss::VolumesIgnored {
+            track: track_id,
+            notes: vol_changes,
+        });
+    }
+    losses.extend(
+        dropped
+            .into_iter()
+            .map(|(instrument, notes)| Loss::DrumsDropped { instrument, notes }),
+    );
+    apply_pans(&mut track.base.events, pans, track_id, losses);
+}
@@ -1,7 +1,10 @@
-use crate::{
-    Sample, StereoSample,
-    song::{LoadError, Song},
-    track::Track as _,
+use {
+    crate::{
+        DrumKit, Event, Interpolation, Pan, PianoKey, Sample, StereoSample, Track, math,
+        song::{LoadError, Song},
+        track::{TrackId, Voices, piano_keys},
+    },
+    alloc::{sync::Arc, vec::Vec},
 };
 
 /// PMD music player
@@ -10,72 +13,921 @@ pub struct Player {
     /// When it reaches zero, we execute the next event
     wait_timer: u32,
     /// Index of event to process next
+    ///
+    /// When it's at or past the end of the repeat range, playback continues at the start of
+    /// the repeat range.
     pub event_cursor: u32,
     /// The currently loaded song
-    pub song: Song,
+    ///
+    /// The song can be shared with other players or an editor. Replacing it keeps the voices
+    /// playing, so edits can be made during playback.
+    pub song: Arc<Song>,
+    /// Playback state of each track, indexed by [`TrackId::index`]
+    voices: [Voices; 4],
+    /// Voices of notes played with [`Player::preview`], indexed by [`TrackId::index`]
+    preview_voices: [Voices; 4],
+    /// How the floating point mix bus is clipped (see [`Player::render_next_f32`])
+    pub clipping: Clipping,
+    /// Mixing controls for each track, indexed by [`TrackId::index`]
+    track_controls: [TrackControls; 4],
+    /// Whether and how many times the repeat range is played
+    pub loop_mode: LoopMode,
+    /// How many times playback jumped back to the start of the repeat range
+    loops_done: u32,
+    /// Playback reached the end of the song, and won't loop anymore
+    finished: bool,
+    /// Fade-out applied at the end of the song, unless it loops forever
+    pub fade: Option<Fade>,
+    /// How event durations are rounded to whole samples
+    pub timing: Timing,
+    /// Factor the tempo of the song is multiplied with, without modifying the song
+    ///
+    /// For example, 2.0 plays twice as fast. The factor is rounded to a multiple of 1/65536,
+    /// and factors below that play at the slowest possible speed.
+    ///
+    /// Positions and durations (like [`Player::position`]) are measured at the current speed,
+    /// so they jump when it changes.
+    pub speed: f64,
+    /// Semitones all melody tracks are shifted by, in addition to [`TrackControls::transpose`]
+    pub transpose: i8,
+    /// Whether playback events are collected for [`Player::drain_events`]
+    ///
+    /// This is off by default, so the queue doesn't grow if nobody drains it.
+    pub report_events: bool,
+    /// Playback events that haven't been drained yet
+    event_queue: Vec<PlaybackEvent>,
+}
+
+/// Something that happened during playback, reported by [`Player::drain_events`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaybackEvent {
+    /// Offset of the stereo frame this happened at, within the buffer passed to the render call
+    /// (like [`Player::render_next`]) that played it
+    ///
+    /// The event affects the frame at this offset and all frames after it.
+    pub offset: usize,
+    /// Playback position of the frame this happened at (see [`Player::position`])
+    pub position: u64,
+    /// What happened
+    pub kind: PlaybackEventKind,
+}
+
+/// The kinds of [`PlaybackEvent`]
+///
+/// Events of the song are reported for all tracks, regardless of their [`TrackControls`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackEventKind {
+    /// Playback jumped from the end of the repeat range back to its start
+    LoopWrap {
+        /// How many times playback has looped, including this time
+        loops_done: u32,
+    },
+    /// The event at this index of the song started playing
+    ///
+    /// This is reported before the notes and pan changes of the event.
+    EventStart {
+        /// Index of the event
+        index: u32,
+    },
+    /// A key was pressed on a track
+    NoteOn {
+        /// The track playing the note
+        track: TrackId,
+        /// The key that was pressed
+        key: PianoKey,
+    },
+    /// The pan position of a track was set
+    PanChange {
+        /// The track whose pan was set
+        track: TrackId,
+        /// The new pan position
+        pan: Pan,
+    },
+    /// Playback reached the end of the song, and won't loop anymore
+    SongEnd,
+}
+
+/// How the duration of events is rounded to whole samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timing {
+    /// Events start at the sample closest to (but not after) their exact time, so the
+    /// average event rate matches the song tempo at any sample rate
+    Exact,
+    /// Every event lasts the truncated number of samples plus one, like the original player.
+    ///
+    /// Playback drifts slower than the song tempo.
+    #[default]
+    Truncated,
+}
+
+/// A fade-out at the end of a song
+///
+/// The fade happens during the last pass through the repeat range, and reaches silence at the
+/// end of the song. If the repeat range is shorter than the fade, the fade is shortened to fit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fade {
+    /// Duration of the fade in milliseconds
+    pub duration_ms: u32,
+    /// How the volume decreases over the duration of the fade
+    pub curve: FadeCurve,
+}
+
+/// How the volume decreases over the duration of a [`Fade`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FadeCurve {
+    /// The amplitude decreases linearly
+    #[default]
+    Linear,
+    /// The amplitude decreases by the same number of decibels over time, down to -60 dB
+    Exponential,
+    /// The amplitude follows half a cosine wave, starting and ending smoothly
+    Cosine,
+}
+
+impl FadeCurve {
+    /// Gain at `t` (from 0.0 to 1.0) into the fade
+    fn gain(self, t: f64) -> f64 {
+        match self {
+            Self::Linear => 1.0 - t,
+            Self::Exponential => math::pow(10.0, -3.0 * t),
+            Self::Cosine => 0.5 * (1.0 + math::cos(core::f64::consts::PI * t)),
+        }
+    }
+}
+
+/// Whether and how many times the repeat range of a song is played
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoopMode {
+    /// Keep looping the repeat range forever
+    #[default]
+    Forever,
+    /// Jump back to the start of the repeat range this many times, then stop at its end
+    Times(u32),
+}
+
+impl LoopMode {
+    /// Play through the song once, stopping at the end of the repeat range
+    pub const ONCE: Self = Self::Times(0);
+}
+
+/// Mixing controls of a track, which are applied during playback without modifying the song
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackControls {
+    /// Whether the track is silenced
+    pub mute: bool,
+    /// If any track is soloed, only soloed tracks are audible
+    pub solo: bool,
+    /// Linear gain applied to the output of the track
+    pub gain: f32,
+    /// Semitones the keys of the track are shifted by (ignored by the percussion track)
+    ///
+    /// Keys shifted out of the range of piano keys continue into the neighboring octaves.
+    pub transpose: i8,
+}
+
+impl Default for TrackControls {
+    fn default() -> Self {
+        Self {
+            mute: false,
+            solo: false,
+            gain: 1.0,
+            transpose: 0,
+        }
+    }
+}
+
+/// How the output of the floating point mix bus is clipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Clipping {
+    /// No clipping, samples can exceed the `-1.0..=1.0` range
+    #[default]
+    None,
+    /// Clamp samples to the `-1.0..=1.0` range
+    Hard,
+    /// Smoothly compress samples above half of full scale, so they never exceed `-1.0..=1.0`
+    Soft,
+}
+
+impl Clipping {
+    fn apply(self, sample: f32) -> f32 {
+        match self {
+            Self::None => sample,
+            Self::Hard => sample.clamp(-1.0, 1.0),
+            Self::Soft => {
+                const KNEE: f32 = 0.5;
+                let abs = sample.abs();
+                if abs <= KNEE {
+                    sample
+                } else {
+                    // tanh has a slope of 1 at zero, so this is continuous in value and slope
+                    let over = (abs - KNEE) / (1.0 - KNEE);
+                    math::fmaf(1.0 - KNEE, math::tanhf(over), KNEE).copysign(sample)
+                }
+            }
+        }
+    }
+}
+
+/// How much the phase of the voices advances with each output sample, in the arithmetic
+/// they're rendered with
+#[derive(Clone, Copy)]
+enum SampPhase {
+    Float(f64),
+    /// 32.32 fixed point
+    Fixed(u64),
+}
+
+impl SampPhase {
+    /// Render the next sample of `voices` into `out`, like [`Track::render_next`]
+    fn render(self, track: &dyn Track, voices: &mut Voices, out: &mut StereoSample) {
+        match self {
+            Self::Float(samp_phase) => track.render_next(voices, out, samp_phase),
+            Self::Fixed(samp_phase) => track.render_next_fixed(voices, out, samp_phase),
+        }
+    }
+    /// Mix the next sample of `voices` into `out`, like [`Track::render_next_f32`]
+    fn render_f32(self, track: &dyn Track, voices: &mut Voices, out: &mut [f32; 2]) {
+        match self {
+            Self::Float(samp_phase) => track.render_next_f32(voices, out, samp_phase),
+            Self::Fixed(samp_phase) => {
+                let mut sample = [0; 2];
+                track.render_next_fixed(voices, &mut sample, samp_phase);
+                for (out, s) in out.iter_mut().zip(sample) {
+                    *out += f32::from(s);
+                }
+            }
+        }
+    }
+}
+
+/// Convert a sample from the floating point mix bus into an integer sample
+///
+/// Samples outside of the `-1.0..=1.0` range saturate.
+#[must_use]
+pub fn sample_from_f32(sample: f32) -> Sample {
+    // Truncation is expected, and float to int casts saturate
+    #[expect(clippy::cast_possible_truncation)]
+    {
+        (sample * 32_768.0) as Sample
+    }
 }
 
 impl Player {
     /// Create a new `Player` with a song loaded from `data`.
     ///
-    /// # Panics
-    /// - If the file is too short
-    ///
     /// # Errors
     ///
-    /// - If the file doesn't have the proper magic marker (`PMD`)
+    /// - If the song fails to load (see [`Song::load`])
     pub fn new(data: &[u8], sample_rate: u32) -> Result<Self, LoadError> {
-        Ok(Self {
+        Ok(Self::from_song(Song::load(data)?, sample_rate))
+    }
+    /// Create a new `Player` playing `song`, which can be shared (see [`Player::song`]).
+    #[must_use]
+    pub fn from_song(song: impl Into<Arc<Song>>, sample_rate: u32) -> Self {
+        Self {
             sample_rate,
             wait_timer: 0,
             event_cursor: 0,
-            song: Song::load(data)?,
-        })
+            song: song.into(),
+            voices: Default::default(),
+            preview_voices: Default::default(),
+            clipping: Clipping::None,
+            track_controls: [TrackControls::default(); _],
+            loop_mode: LoopMode::Forever,
+            loops_done: 0,
+            finished: false,
+            fade: None,
+            timing: Timing::Truncated,
+            speed: 1.0,
+            transpose: 0,
+            report_events: false,
+            event_queue: Vec::new(),
+        }
     }
     /// Advances playback and renders samples into `buf`.
-    pub fn render_next(&mut self, buf: &mut [Sample]) {
+    ///
+    /// This mixes the voices like the original player does, truncating each of them to an
+    /// integer sample, and saturating the sum.
+    ///
+    /// Returns the number of stereo frames written, which is less than requested if the song
+    /// finished (see [`Player::is_finished`]). The rest of `buf` is left untouched.
+    pub fn render_next(&mut self, buf: &mut [Sample]) -> usize {
+        let mut written = 0;
         for sample in buf.as_chunks_mut().0 {
-            self.tick();
+            if !self.tick(Some(written)) {
+                break;
+            }
             *sample = self.next_sample();
+            written += 1;
+        }
+        written
+    }
+    /// Advances playback and renders samples into `buf` using a floating point mix bus.
+    ///
+    /// Full scale is `-1.0..=1.0`. Voices are mixed without quantization, and the sum is only
+    /// clipped according to [`Player::clipping`].
+    ///
+    /// Returns the number of stereo frames written, like [`Player::render_next`].
+    pub fn render_next_f32(&mut self, buf: &mut [f32]) -> usize {
+        let mut written = 0;
+        for sample in buf.as_chunks_mut().0 {
+            if !self.tick(Some(written)) {
+                break;
+            }
+            *sample = self.next_sample_f32();
+            written += 1;
+        }
+        written
+    }
+    /// Advances playback and renders each track separately into `buf`.
+    ///
+    /// Each element of `buf` is a stereo frame of each track, indexed by [`TrackId::index`]
+    /// (see [`Player::next_stems`]).
+    ///
+    /// Returns the number of frames written, like [`Player::render_next`].
+    pub fn render_stems(&mut self, buf: &mut [[StereoSample; 4]]) -> usize {
+        let mut written = 0;
+        for stems in buf {
+            if !self.tick(Some(written)) {
+                break;
+            }
+            *stems = self.next_stems();
+            written += 1;
+        }
+        written
+    }
+    /// Advances playback and renders each track separately into `buf` using floating point
+    /// samples.
+    ///
+    /// See [`Player::render_stems`] and [`Player::next_stems_f32`].
+    pub fn render_stems_f32(&mut self, buf: &mut [[[f32; 2]; 4]]) -> usize {
+        let mut written = 0;
+        for stems in buf {
+            if !self.tick(Some(written)) {
+                break;
+            }
+            *stems = self.next_stems_f32();
+            written += 1;
         }
+        written
+    }
+    /// Whether playback reached the end of the song, and won't loop anymore
+    /// (see [`Player::loop_mode`])
+    #[must_use]
+    pub const fn is_finished(&self) -> bool {
+        self.finished
     }
 
-    fn tick(&mut self) {
-        if self.wait_timer == 0 {
-            let event_wait_samples = self.sample_rate * self.song.event_wait_ms / 1000;
-            self.wait_timer = event_wait_samples;
+    /// How many stereo frames it takes to play `n_events` events from the start of the song
+    ///
+    /// Event `n` starts at `frames_for_events(n)`, counting events played in previous loops.
+    fn frames_for_events(&self, n_events: u64) -> u64 {
+        let (num, den) = self.event_frames();
+        let frames = match self.timing {
+            // Every event lasts at least one sample
+            Timing::Exact => u128::from(n_events).saturating_mul(num.max(den)) / den,
+            // An event is processed on the tick after the wait timer reached zero
+            Timing::Truncated => u128::from(n_events).saturating_mul(num / den + 1),
+        };
+        u64::try_from(frames).unwrap_or(u64::MAX)
+    }
+    /// The inverse of [`Player::frames_for_events`]: the index of the event playing at `pos`
+    fn event_at_frame(&self, pos: u64) -> u64 {
+        let (num, den) = self.event_frames();
+        let event = match self.timing {
+            Timing::Exact => ((u128::from(pos) + 1).saturating_mul(den) - 1) / num.max(den),
+            Timing::Truncated => u128::from(pos) / (num / den + 1),
+        };
+        u64::try_from(event).unwrap_or(u64::MAX)
+    }
+    /// The exact duration of an event in stereo frames, as a numerator and denominator
+    fn event_frames(&self) -> (u128, u128) {
+        // The speed is applied as a fixed point number, so a speed of 1.0 keeps the timing exact
+        const SPEED_ONE: u32 = 1 << 16;
+        // Float to int casts saturate, and NaN becomes zero. Limiting the speed to 64 bits keeps
+        // the product below from overflowing.
+        #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let speed = u128::from((math::round(self.speed * f64::from(SPEED_ONE)) as u64).max(1));
+        let rate = u128::from(self.sample_rate);
+        let wait_ms = u128::from(self.song.event_wait_ms);
+        (rate * wait_ms * u128::from(SPEED_ONE), 1000 * speed)
+    }
+    /// Index of the event at `event_cursor`, counting events played in previous loops
+    fn linear_event_index(&self, event_cursor: u32) -> u64 {
+        let range = &self.song.repeat_range;
+        u64::from(event_cursor)
+            + u64::from(self.loops_done) * u64::from(range.end.saturating_sub(range.start))
+    }
+
+    /// How many stereo frames it takes to play through the repeat range once
+    fn loop_frames(&self) -> u64 {
+        let range = &self.song.repeat_range;
+        self.frames_for_events(u64::from(range.end.saturating_sub(range.start)))
+    }
 
-            for track in &mut self.song.melody_tracks {
-                track.do_event_at_idx(self.event_cursor as usize);
+    /// The position of the next sample to be rendered, in samples from the start of playback
+    ///
+    /// Each time the song loops, the position keeps increasing by the length of the repeat
+    /// range, so this is the elapsed playback time.
+    #[must_use]
+    pub fn position(&self) -> u64 {
+        // While waiting, the cursor is already past the event being played
+        self.frames_for_events(self.linear_event_index(self.event_cursor))
+            .saturating_sub(u64::from(self.wait_timer))
+    }
+    /// The position of the next sample to be rendered, in milliseconds from the start of
+    /// playback
+    ///
+    /// See [`Player::position`].
+    #[must_use]
+    pub fn position_ms(&self) -> u64 {
+        self.position() * 1000 / u64::from(self.sample_rate)
+    }
+    /// The total length of playback in samples, or `None` if the song loops forever
+    ///
+    /// This doesn't include the time it takes for the notes of the last event to ring out.
+    #[must_use]
+    pub fn duration(&self) -> Option<u64> {
+        match self.loop_mode {
+            LoopMode::Forever => None,
+            LoopMode::Times(n) => {
+                let range = &self.song.repeat_range;
+                let loop_len = range.end.saturating_sub(range.start);
+                Some(
+                    self.frames_for_events(
+                        u64::from(range.end) + u64::from(n) * u64::from(loop_len),
+                    ),
+                )
             }
-            self.song
-                .percussion_track
-                .do_event_at_idx(self.event_cursor as usize);
-            self.event_cursor += 1;
+        }
+    }
+    /// The gain of the fade-out at the current position
+    fn fade_gain(&self) -> Option<f64> {
+        let fade = self.fade?;
+        let end = self.duration()?;
+        let fade_frames = u64::from(self.sample_rate) * u64::from(fade.duration_ms) / 1000;
+        let fade_start = end.saturating_sub(fade_frames.min(self.loop_frames()));
+        let pos = self.position();
+        if pos < fade_start {
+            return None;
+        }
+        // Precision loss is negligible for positions within a song
+        #[expect(clippy::cast_precision_loss)]
+        let t = (pos - fade_start) as f64 / (end - fade_start).max(1) as f64;
+        Some(fade.curve.gain(t.min(1.0)))
+    }
+    /// Seek to `pos` samples from the start of playback
+    ///
+    /// This is the inverse of [`Player::position`], so positions past the end of the repeat
+    /// range land in the repeat range, with the loop count adjusted accordingly.
+    /// Seeking past the end of a song that doesn't loop forever finishes playback.
+    ///
+    /// Notes that are still ringing at the seek position are restored by playing the
+    /// preceding events silently.
+    pub fn seek(&mut self, pos: u64) {
+        let range = self.song.repeat_range.clone();
+        let target = self.event_at_frame(pos);
+        let (event, loops) = self.event_cursor_at(target);
+        if let LoopMode::Times(n) = self.loop_mode
+            && loops > u64::from(n)
+        {
+            self.loops_done = n;
+            self.event_cursor = range.end;
+            self.wait_timer = 0;
+            self.finished = true;
+            return;
+        }
+        // The target might have been clamped to the last event
+        let loop_len = u64::from(range.end.saturating_sub(range.start));
+        let target = event + loops * loop_len;
+        // Start early enough for the longest possible note to ring into the seek position
+        let samp_phase = self.samp_phase();
+        let max_note_len = self
+            .song
+            .tracks()
+            .into_iter()
+            .zip(&self.voices)
+            .flat_map(|(track, voices)| {
+                piano_keys().map(move |key| track.note_duration(voices, key))
+            })
+            .fold(0.0, f64::max);
+        // Note durations are positive, and way below the range of u64
+        #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let lookback_frames = math::ceil(max_note_len / samp_phase) as u64;
+        let lookback = self.event_at_frame(lookback_frames) + 1;
+        // Going back might cross the loop point, so the lookback is counted in linear events
+        let first_event = target.saturating_sub(lookback);
+        let (cursor, loops) = self.event_cursor_at(first_event);
+        self.loops_done = u32::try_from(loops).unwrap_or(u32::MAX);
+        // Event indices are bounded by the event count, which is 32 bit in the file format
+        #[expect(clippy::cast_possible_truncation)]
+        {
+            self.event_cursor = cursor as u32;
+        }
+        self.wait_timer = 0;
+        self.finished = false;
+        let looped_range = (loops > 0).then_some(range.start as usize..range.end as usize);
+        for (track, voices) in self.song.tracks().into_iter().zip(&mut self.voices) {
+            voices.reset(
+                track.base(),
+                self.event_cursor as usize,
+                looped_range.clone(),
+            );
+        }
+        let first_frame = self.frames_for_events(first_event);
+        let event_end = self.frames_for_events(target + 1);
+        for _ in first_frame..pos.min(event_end - 1) {
+            self.tick(None);
+            self.advance_song_voices();
+        }
+    }
+    /// The event cursor and loop count at which the event with the linear index `event` plays
+    ///
+    /// This is the inverse of [`Player::linear_event_index`]. Events past the end of a song that
+    /// doesn't loop are clamped to the last event.
+    fn event_cursor_at(&self, event: u64) -> (u64, u64) {
+        let range = &self.song.repeat_range;
+        let loop_len = u64::from(range.end.saturating_sub(range.start));
+        let end = u64::from(range.end);
+        if event >= end && loop_len > 0 {
+            let past_end = event - end;
+            (
+                end - loop_len + past_end % loop_len,
+                past_end / loop_len + 1,
+            )
+        } else {
+            let last_event = self.n_events().saturating_sub(1) as u64;
+            (event.min(last_event), 0)
+        }
+    }
+    /// Seek to `ms` milliseconds from the start of the song
+    ///
+    /// See [`Player::seek`].
+    pub fn seek_ms(&mut self, ms: u64) {
+        self.seek(ms * u64::from(self.sample_rate) / 1000);
+    }
+
+    /// Advance playback by one sample. Returns `false` if the song is finished.
+    ///
+    /// `offset` is the offset of the sample in the buffer being rendered, if playback events
+    /// should be reported.
+    fn tick(&mut self, offset: Option<usize>) -> bool {
+        if self.finished {
+            return false;
+        }
+        if self.wait_timer == 0 {
+            let offset = offset.filter(|_| self.report_events);
+            // Wrapping around right before processing the next event (rather than right after
+            // processing the last one) keeps the position monotonic until the loop point
             if self.event_cursor >= self.song.repeat_range.end {
+                if let LoopMode::Times(n) = self.loop_mode
+                    && self.loops_done >= n
+                {
+                    self.finished = true;
+                    self.report(offset, PlaybackEventKind::SongEnd);
+                    return false;
+                }
+                self.loops_done = self.loops_done.saturating_add(1);
                 self.event_cursor = self.song.repeat_range.start;
+                let loops_done = self.loops_done;
+                self.report(offset, PlaybackEventKind::LoopWrap { loops_done });
+            }
+            if offset.is_some() {
+                self.report_song_event(offset);
             }
+            let event = self.linear_event_index(self.event_cursor);
+            let frames = self.frames_for_events(event + 1) - self.frames_for_events(event);
+            // Both ends saturate for songs longer than the range of u64
+            self.wait_timer = u32::try_from(frames.saturating_sub(1)).unwrap_or(u32::MAX);
+
+            for (track, voices) in self.song.tracks().into_iter().zip(&mut self.voices) {
+                track.do_event_at_idx(voices, self.event_cursor as usize);
+            }
+            self.event_cursor += 1;
         } else {
             self.wait_timer -= 1;
         }
+        true
+    }
+    /// Queue a playback event happening at `offset`, if events are reported
+    fn report(&mut self, offset: Option<usize>, kind: PlaybackEventKind) {
+        if let Some(offset) = offset {
+            self.event_queue.push(PlaybackEvent {
+                offset,
+                position: self.position(),
+                kind,
+            });
+        }
+    }
+    /// Queue the playback events of the song event at the event cursor, which is about to play
+    fn report_song_event(&mut self, offset: Option<usize>) {
+        let index = self.event_cursor;
+        self.report(offset, PlaybackEventKind::EventStart { index });
+        for track in TrackId::ALL {
+            let events = match track {
+                TrackId::Melody(idx) => &self.song.melody_tracks[usize::from(idx)].base.events,
+                TrackId::Percussion => &self.song.percussion_track.base.events,
+            };
+            // Tracks shorter than the song play empty events past their end
+            let Some(&event) = events.get(index as usize) else {
+                continue;
+            };
+            for key in event.keys_down() {
+                self.report(offset, PlaybackEventKind::NoteOn { track, key });
+            }
+            if let Some(pan) = event.pan_pos() {
+                self.report(offset, PlaybackEventKind::PanChange { track, pan });
+            }
+        }
+    }
+    /// Take the playback events that happened since the last call, in the order they happened
+    ///
+    /// Events are only collected while [`Player::report_events`] is enabled. Each render call
+    /// (like [`Player::render_next`]) reports offsets relative to its own buffer, so the events
+    /// should be drained after each render call.
+    pub fn drain_events(&mut self) -> alloc::vec::Drain<'_, PlaybackEvent> {
+        self.event_queue.drain(..)
+    }
+    /// Apply [`Player::transpose`] and [`TrackControls::transpose`] to the voices
+    fn apply_transpose(&mut self) {
+        let voices = self.voices.iter_mut().zip(&mut self.preview_voices);
+        for (ctl, (voices, preview_voices)) in self.track_controls.iter().zip(voices) {
+            let transpose = i16::from(self.transpose) + i16::from(ctl.transpose);
+            voices.transpose = transpose;
+            preview_voices.transpose = transpose;
+        }
     }
     /// Render a sample according the current state of the player
     pub fn next_sample(&mut self) -> StereoSample {
+        self.apply_transpose();
         let mut sample = [0; 2];
-        let samp_phase = 22_050. / f64::from(self.sample_rate);
-        for track in &mut self.song.melody_tracks {
-            track.render_next(&mut sample, samp_phase);
+        let samp_phase = self.voice_samp_phase();
+        let gains = self.effective_gains();
+        let tracks = self.song.tracks().into_iter().zip(&mut self.voices);
+        for ((track, voices), gain) in tracks.zip(gains) {
+            // Unity gain is exact, and mixing directly into the output keeps the saturation
+            // behavior of the original player
+            #[expect(clippy::float_cmp)]
+            if gain == 1.0 {
+                samp_phase.render(track, voices, &mut sample);
+            } else {
+                // Muted tracks are still rendered, so they are in sync when unmuted
+                let mut track_sample = [0; 2];
+                samp_phase.render(track, voices, &mut track_sample);
+                for (out, s) in sample.iter_mut().zip(track_sample) {
+                    // Truncation is expected, and float to int casts saturate
+                    #[expect(clippy::cast_possible_truncation)]
+                    let s = (f32::from(s) * gain) as Sample;
+                    *out = out.saturating_add(s);
+                }
+            }
         }
-        self.song
-            .percussion_track
-            .render_next(&mut sample, samp_phase);
+        if let Some(gain) = self.fade_gain() {
+            // Truncation is expected, and float to int casts saturate
+            #[expect(clippy::cast_possible_truncation)]
+            let fade = |s: Sample| (f64::from(s) * gain) as Sample;
+            sample = sample.map(fade);
+        }
+        // Previews are mixed after the fade and the track controls, so they're always audible
+        self.render_previews(&mut sample, samp_phase);
         sample
     }
+    /// Render a sample according the current state of the player using a floating point mix bus
+    pub fn next_sample_f32(&mut self) -> [f32; 2] {
+        self.apply_transpose();
+        let mut sample = [0.0; 2];
+        let samp_phase = self.voice_samp_phase();
+        let gains = self.effective_gains();
+        let tracks = self.song.tracks().into_iter().zip(&mut self.voices);
+        for ((track, voices), gain) in tracks.zip(gains) {
+            let mut track_sample = [0.0; 2];
+            samp_phase.render_f32(track, voices, &mut track_sample);
+            for (out, s) in sample.iter_mut().zip(track_sample) {
+                *out = math::fmaf(s, gain, *out);
+            }
+        }
+        if let Some(gain) = self.fade_gain() {
+            // The gain is within 0.0..=1.0, so it fits into f32
+            #[expect(clippy::cast_possible_truncation)]
+            let gain = gain as f32;
+            sample = sample.map(|s| s * gain);
+        }
+        let tracks = self.song.tracks().into_iter().zip(&mut self.preview_voices);
+        for (track, voices) in tracks {
+            samp_phase.render_f32(track, voices, &mut sample);
+        }
+        sample.map(|s| self.clipping.apply(s / 32_768.0))
+    }
+    /// Render a sample of each track separately, indexed by [`TrackId::index`]
+    ///
+    /// Each track is rendered like [`Player::next_sample`] renders the mix, with its
+    /// [`TrackControls`] and the [`Fade`] applied. Previews keep playing, but aren't part of
+    /// any stem.
+    pub fn next_stems(&mut self) -> [StereoSample; 4] {
+        self.apply_transpose();
+        let samp_phase = self.voice_samp_phase();
+        let gains = self.effective_gains();
+        let fade = self.fade_gain().unwrap_or(1.0);
+        let mut stems = [[0; 2]; 4];
+        let tracks = self.song.tracks().into_iter().zip(&mut self.voices);
+        for (((track, voices), gain), stem) in tracks.zip(gains).zip(&mut stems) {
+            samp_phase.render(track, voices, stem);
+            let gain = f64::from(gain) * fade;
+            // Truncation is expected, and float to int casts saturate
+            #[expect(clippy::cast_possible_truncation)]
+            {
+                *stem = stem.map(|s| (f64::from(s) * gain) as Sample);
+            }
+        }
+        self.render_previews(&mut [0; 2], samp_phase);
+        stems
+    }
+    /// Render a sample of each track separately using floating point samples
+    ///
+    /// Like [`Player::next_stems`], but each track is rendered like
+    /// [`Player::next_sample_f32`] renders the mix. The stems aren't clipped, as
+    /// [`Player::clipping`] only applies to the mix.
+    pub fn next_stems_f32(&mut self) -> [[f32; 2]; 4] {
+        self.apply_transpose();
+        let samp_phase = self.voice_samp_phase();
+        let gains = self.effective_gains();
+        // The gain is within 0.0..=1.0, so it fits into f32
+        #[expect(clippy::cast_possible_truncation)]
+        let fade = self.fade_gain().unwrap_or(1.0) as f32;
+        let mut stems = [[0.0; 2]; 4];
+        let tracks = self.song.tracks().into_iter().zip(&mut self.voices);
+        for (((track, voices), gain), stem) in tracks.zip(gains).zip(&mut stems) {
+            samp_phase.render_f32(track, voices, stem);
+            *stem = stem.map(|s| s * gain * fade / 32_768.0);
+        }
+        self.render_previews(&mut [0; 2], samp_phase);
+        stems
+    }
+    /// Advance the voices of the song by one sample without rendering it, leaving the previews
+    /// where they are
+    fn advance_song_voices(&mut self) {
+        self.apply_transpose();
+        let samp_phase = self.voice_samp_phase();
+        for (track, voices) in self.song.tracks().into_iter().zip(&mut self.voices) {
+            samp_phase.render(track, voices, &mut [0; 2]);
+        }
+    }
+    /// Mix the next sample of the previews into `sample`
+    fn render_previews(&mut self, sample: &mut StereoSample, samp_phase: SampPhase) {
+        let tracks = self.song.tracks().into_iter().zip(&mut self.preview_voices);
+        for (track, voices) in tracks {
+            samp_phase.render(track, voices, sample);
+        }
+    }
+    /// Play the keys of `event` with the instrument of `track`, without affecting the song
+    ///
+    /// Previews have their own voices, so they don't cut off the notes of the song, and the
+    /// pan of `event` only applies to previews of `track`. They can be used to audition notes
+    /// in an editor, or as sound effects.
+    ///
+    /// Previews are rendered along with the song, regardless of the [`TrackControls`] and the
+    /// [`Fade`]. Once the song is finished, only [`Player::next_sample`] and
+    /// [`Player::next_sample_f32`] keep rendering them.
+    ///
+    /// # Panics
+    ///
+    /// - If `track` refers to a melody track that doesn't exist
+    pub fn preview(&mut self, track: TrackId, event: Event) {
+        let voices = &mut self.preview_voices[track.index()];
+        self.song.tracks()[track.index()].do_event(voices, event);
+    }
+    /// Silence all previews, and center their pan
+    pub fn stop_previews(&mut self) {
+        for (track, voices) in self.song.tracks().into_iter().zip(&mut self.preview_voices) {
+            voices.reset(track.base(), 0, None);
+        }
+    }
+    /// The playback state of the voices of `track`
+    ///
+    /// # Panics
+    ///
+    /// - If `track` refers to a melody track that doesn't exist
+    #[must_use]
+    pub const fn voices(&self, track: TrackId) -> &Voices {
+        &self.voices[track.index()]
+    }
+    /// The playback state of the preview voices of `track` (see [`Player::preview`])
+    ///
+    /// # Panics
+    ///
+    /// - If `track` refers to a melody track that doesn't exist
+    #[must_use]
+    pub const fn preview_voices(&self, track: TrackId) -> &Voices {
+        &self.preview_voices[track.index()]
+    }
+    /// The waveform interpolation of the melody tracks
+    #[must_use]
+    pub const fn interpolation(&self) -> Interpolation {
+        self.voices[0].interpolation()
+    }
+    /// Set the waveform interpolation of all melody tracks
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        for voices in self.voices.iter_mut().chain(&mut self.preview_voices) {
+            voices.set_interpolation(interpolation);
+        }
+    }
+    /// Whether the voices are rendered with fixed point math (see [`Player::set_fixed_point`])
+    #[must_use]
+    pub const fn fixed_point(&self) -> bool {
+        self.voices[0].fixed_point()
+    }
+    /// Set whether the voices of all tracks are rendered with fixed point math
+    ///
+    /// This is meant for targets without a floating point unit. In fixed point mode, rendering
+    /// the voices only takes integer math: phases and timers are 32.32 fixed point, and
+    /// volumes and pans are applied as 16.16 fixed point gains taken from a table. Melody
+    /// waveforms are always sampled like [`Interpolation::Nearest`]. Processing events, and
+    /// applying a [`Fade`] or a [`TrackControls::gain`] other than 1.0, still takes a few
+    /// float operations. The floating point mix bus (like [`Player::render_next_f32`]) mixes
+    /// the tracks after rendering each of them like [`Player::render_next`].
+    ///
+    /// # Accuracy
+    ///
+    /// At sample rates of 22050 Hz times a power of two (like 11025, 44100 or 88200 Hz), the
+    /// phases and timers of both modes are exact. Every voice of a melody track, or of a
+    /// percussion track with drum samples at such a rate (like the built-in ones), is then
+    /// within 1 of the floating point mode with [`Interpolation::Nearest`], as long as the
+    /// track volume is within `0..=300`. So each frame of [`Player::render_next`] is within the
+    /// number of voices playing in it (including previews) of the floating point mode.
+    ///
+    /// At other sample rates, the phase steps are rounded to a 2^32th of a voice sample. The
+    /// pitch stays the same for all practical purposes, but a voice may reach the next
+    /// waveform point a sample earlier or later than in floating point mode.
+    pub fn set_fixed_point(&mut self, fixed_point: bool) {
+        for voices in self.voices.iter_mut().chain(&mut self.preview_voices) {
+            voices.set_fixed_point(fixed_point);
+        }
+    }
+    /// The samples the percussion track is played with
+    #[must_use]
+    pub const fn drum_kit(&self) -> &DrumKit {
+        &self.voices[TrackId::Percussion.index()].kit
+    }
+    /// Play the percussion track (and its previews) with the samples of `kit`
+    ///
+    /// The kit belongs to the player, so it stays when [`Player::song`] is replaced.
+    pub fn set_drum_kit(&mut self, kit: DrumKit) {
+        let idx = TrackId::Percussion.index();
+        self.preview_voices[idx].kit = kit.clone();
+        self.voices[idx].kit = kit;
+    }
+    /// How much the phase of the 22050 Hz voices advances with each output sample
+    fn samp_phase(&self) -> f64 {
+        22_050. / f64::from(self.sample_rate)
+    }
+    /// [`Player::samp_phase`] in the arithmetic the voices are rendered with
+    fn voice_samp_phase(&self) -> SampPhase {
+        if self.fixed_point() {
+            SampPhase::Fixed((22_050 << 32) / u64::from(self.sample_rate.max(1)))
+        } else {
+            SampPhase::Float(self.samp_phase())
+        }
+    }
+    /// The mixing controls of `track`
+    ///
+    /// # Panics
+    ///
+    /// - If `track` refers to a melody track that doesn't exist
+    #[must_use]
+    pub const fn track_controls(&self, track: TrackId) -> &TrackControls {
+        &self.track_controls[track.index()]
+    }
+    /// The mixing controls of `track`, for modification
+    ///
+    /// # Panics
+    ///
+    /// - If `track` refers to a melody track that doesn't exist
+    pub const fn track_controls_mut(&mut self, track: TrackId) -> &mut TrackControls {
+        &mut self.track_controls[track.index()]
+    }
+    /// The gain of each track after applying mute and solo
+    fn effective_gains(&self) -> [f32; 4] {
+        let any_solo = self.track_controls.iter().any(|ctl| ctl.solo);
+        self.track_controls.map(|ctl| {
+            if ctl.mute || (any_solo && !ctl.solo) {
+                0.0
+            } else {
+                ctl.gain
+            }
+        })
+    }
 
     /// Returns number of events in the song
+    ///
+    /// This is the length of the longest track. Shorter tracks play empty events past their
+    /// end, like they're padded when saving (see [`Song::to_bytes`]).
     #[must_use]
     pub fn n_events(&self) -> usize {
-        // Each track has the same length, we just use the percussion track for simplicity
-        self.song.percussion_track.base.events.len()
+        self.song
+            .tracks()
+            .iter()
+            .map(|track| track.base().events.len())
+            .max()
+            .unwrap_or(0)
     }
 }
@@ -1,25 +1,60 @@
-pub(crate) struct ReadCursor<'a>(pub &'a [u8]);
+use alloc::boxed::Box;
+
+pub(crate) struct ReadCursor<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
 
 impl<'a> ReadCursor<'a> {
-    pub fn next_bytes<const N: usize>(&mut self) -> Option<&'a [u8; N]> {
-        let (bytes, next) = self.0.split_first_chunk()?;
-        self.0 = next;
+    pub const fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+    /// Offset of the next byte to be read, relative to the start of the buffer
+    pub const fn pos(&self) -> usize {
+        self.pos
+    }
+    fn advance(&mut self, amount: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(amount)?;
+        let bytes = self.buf.get(self.pos..end)?;
+        self.pos = end;
         Some(bytes)
     }
-    pub fn next_n<T: bytemuck::AnyBitPattern + bytemuck::NoUninit>(&mut self, n: usize) -> &'a [T] {
-        let (this, next) = bytemuck::cast_slice(self.0).split_at(n);
-        self.0 = bytemuck::cast_slice(next);
-        this
+    pub fn next_bytes<const N: usize>(&mut self) -> Option<&'a [u8; N]> {
+        self.advance(N)?.try_into().ok()
+    }
+    /// Reads `n` consecutive values of `T`, regardless of alignment
+    pub fn next_n<T: bytemuck::AnyBitPattern>(&mut self, n: usize) -> Option<Box<[T]>> {
+        let bytes = self.advance(n.checked_mul(size_of::<T>())?)?;
+        Some(
+            bytes
+                .chunks_exact(size_of::<T>())
+                .map(bytemuck::pod_read_unaligned)
+                .collect(),
+        )
     }
     pub fn next_u8(&mut self) -> Option<u8> {
-        let (&byte, next) = self.0.split_first()?;
-        self.0 = next;
-        Some(byte)
+        self.next_bytes::<1>().map(|&[byte]| byte)
+    }
+    pub fn next_u16_le(&mut self) -> Option<u16> {
+        self.next_bytes().copied().map(u16::from_le_bytes)
     }
     pub fn next_u32_le(&mut self) -> Option<u32> {
         self.next_bytes().copied().map(u32::from_le_bytes)
     }
-    pub fn skip(&mut self, amount: usize) {
-        self.0 = &self.0[amount..];
+    pub fn next_u16_be(&mut self) -> Option<u16> {
+        self.next_bytes().copied().map(u16::from_be_bytes)
+    }
+    pub fn next_u32_be(&mut self) -> Option<u32> {
+        self.next_bytes().copied().map(u32::from_be_bytes)
+    }
+    /// Reads the next `n` bytes
+    pub fn next_slice(&mut self, n: usize) -> Option<&'a [u8]> {
+        self.advance(n)
+    }
+    /// Reads all bytes up to the end of the buffer
+    pub fn rest(&mut self) -> &'a [u8] {
+        let bytes = &self.buf[self.pos..];
+        self.pos = self.buf.len();
+        bytes
     }
 }
@@ -0,0 +1,25 @@
+//! (De)serialization of arrays longer than serde supports out of the box
+//!
+//! Used with `#[serde(with = "crate::serde_array")]`. Arrays are written as sequences.
+
+use {
+    alloc::{format, vec::Vec},
+    serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error},
+};
+
+pub fn serialize<S: Serializer, T: Serialize, const N: usize>(
+    array: &[T; N],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    array.as_slice().serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>, const N: usize>(
+    deserializer: D,
+) -> Result<[T; N], D::Error> {
+    let items = Vec::<T>::deserialize(deserializer)?;
+    let len = items.len();
+    items
+        .try_into()
+        .map_err(|_| D::Error::invalid_length(len, &format!("an array of length {N}").as_str()))
+}
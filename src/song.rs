@@ -1,61 +1,283 @@
-use crate::{MelodyTrack, PercussionTrack, read_cursor::ReadCursor};
+pub use self::validate::{Diagnostic, Location, Problem, Severity};
+
+use {
+    crate::{Event, MelodyTrack, PercussionTrack, Track, math, read_cursor::ReadCursor},
+    alloc::{boxed::Box, vec, vec::Vec},
+};
+
+mod validate;
 
 /// A Piyo Piyo song
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Song {
+    /// Uninterpreted header bytes following the magic marker, preserved for saving
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub header_extra: [u8; 5],
     /// How many milliseconds to wait before next event
     pub event_wait_ms: u32,
     /// Range within which the song repeats
-    pub repeat_range: std::ops::Range<u32>,
+    pub repeat_range: core::ops::Range<u32>,
     /// The melody tracks of the song
     pub melody_tracks: [MelodyTrack; 3],
     /// The percussion track of the song
     pub percussion_track: PercussionTrack,
+    /// Uninterpreted bytes following the events, preserved for saving
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub trailing_bytes: Vec<u8>,
 }
 
 impl Song {
+    /// Create a song with `n_events` empty events, and a simple instrument on each melody track
+    ///
+    /// The tempo is 125 milliseconds per event, and the whole song repeats.
+    #[must_use]
+    pub fn new(n_events: u32) -> Self {
+        let events = || vec![Event::from_bits(0); n_events as usize].into_boxed_slice();
+        let melody_tracks = core::array::from_fn(|_| {
+            let mut track = MelodyTrack::default();
+            // A sine wave at a bit below full scale, so it's bounded to the i8 range
+            #[expect(clippy::cast_possible_truncation)]
+            {
+                track.waveform = core::array::from_fn(|i| {
+                    let angle = core::f64::consts::TAU * f64::from(i as u8) / 256.0;
+                    math::round(math::sin(angle) * 100.0) as i8
+                });
+            }
+            // Decay linearly from the loudest envelope value
+            for (value, i) in track.envelope.iter_mut().zip(0..) {
+                *value = 127 - i * 2;
+            }
+            track.octave = 3;
+            // A quarter of a second at the voice rate of 22050 Hz
+            track.len = 5512;
+            track.base.vol = 250;
+            track.base.events = events();
+            track
+        });
+        let mut percussion_track = PercussionTrack::default();
+        percussion_track.base.vol = 250;
+        percussion_track.base.events = events();
+        Self {
+            header_extra: [0; 5],
+            event_wait_ms: 125,
+            repeat_range: 0..n_events,
+            melody_tracks,
+            percussion_track,
+            trailing_bytes: Vec::new(),
+        }
+    }
     /// Load a PMD music file
     ///
-    /// # Panics
-    /// - If the file is too short
+    /// Any file that can be parsed is loaded, even if it has problems that [`Song::validate`]
+    /// reports, like a repeat range past the last event.
     ///
     /// # Errors
     ///
     /// - If the file doesn't have the proper magic marker (`PMD`)
+    /// - If the file is too short
+    /// - If a field holds a value that can't be represented
     pub fn load(data: &[u8]) -> Result<Self, LoadError> {
-        let mut cur = ReadCursor(data);
+        let mut cur = ReadCursor::new(data);
         let magic = cur.next_bytes();
         if magic != Some(b"PMD") {
             return Err(LoadError::InvalidMagic);
         }
-        cur.skip(5);
-        let millis_per_tick = cur.next_u32_le().ok_or(LoadError::PrematureEof)?;
-        let repeat_tick = cur.next_u32_le().ok_or(LoadError::PrematureEof)?;
-        let end_tick = cur.next_u32_le().ok_or(LoadError::PrematureEof)?;
-        let n_events = cur.next_u32_le().ok_or(LoadError::PrematureEof)? as usize;
+        let header_extra = *read_field(&mut cur, Field::HeaderExtra, ReadCursor::next_bytes)?;
+        let millis_per_tick = read_field(&mut cur, Field::EventWait, ReadCursor::next_u32_le)?;
+        let repeat_tick = read_field(&mut cur, Field::RepeatStart, ReadCursor::next_u32_le)?;
+        let end_tick = read_field(&mut cur, Field::RepeatEnd, ReadCursor::next_u32_le)?;
+        let n_events = read_field(&mut cur, Field::EventCount, ReadCursor::next_u32_le)?;
+        // We can't hold more events than the address space allows anyway
+        let n_events = usize::try_from(n_events).unwrap_or(usize::MAX);
 
-        let mut melody_tracks = std::array::from_fn(|_| MelodyTrack::default());
+        let mut melody_tracks = core::array::from_fn(|_| MelodyTrack::default());
 
-        for track in &mut melody_tracks {
-            track.read(&mut cur)?;
+        for (idx, track) in (0..).zip(&mut melody_tracks) {
+            track.read(&mut cur, idx)?;
         }
 
         let mut percussion_track = PercussionTrack::default();
 
-        percussion_track.base.vol = cur
-            .next_u32_le()
-            .ok_or(LoadError::PrematureEof)?
-            .try_into()
-            .unwrap();
+        percussion_track.base.vol = read_u16_field(&mut cur, Field::PercussionVolume)?;
 
-        for track in &mut melody_tracks {
-            track.base.events = cur.next_n(n_events).into();
+        for (idx, track) in (0..).zip(&mut melody_tracks) {
+            track.base.events = read_events(&mut cur, Field::MelodyEvents(idx), n_events)?;
         }
-        percussion_track.base.events = cur.next_n(n_events).into();
+        percussion_track.base.events = read_events(&mut cur, Field::PercussionEvents, n_events)?;
         Ok(Self {
+            header_extra,
             event_wait_ms: millis_per_tick,
             repeat_range: repeat_tick..end_tick,
             melody_tracks,
             percussion_track,
+            trailing_bytes: cur.rest().to_vec(),
+        })
+    }
+    /// All tracks of the song, in the order of [`TrackId::ALL`](crate::TrackId::ALL)
+    pub(crate) fn tracks(&self) -> [&dyn Track; 4] {
+        let [m0, m1, m2] = &self.melody_tracks;
+        [m0, m1, m2, &self.percussion_track]
+    }
+    /// Serialize the song into the PMD format
+    ///
+    /// Loading a file and serializing it again reproduces the original bytes.
+    ///
+    /// The file format stores a single event count for all tracks, so tracks with fewer
+    /// events than the longest track are padded with empty events.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let tracks = self
+            .melody_tracks
+            .iter()
+            .map(|track| &track.base)
+            .chain([&self.percussion_track.base]);
+        let n_events = tracks
+            .clone()
+            .map(|base| base.events.len())
+            .max()
+            .unwrap_or(0);
+        let mut out = Vec::new();
+        out.extend_from_slice(b"PMD");
+        out.extend_from_slice(&self.header_extra);
+        out.extend_from_slice(&self.event_wait_ms.to_le_bytes());
+        out.extend_from_slice(&self.repeat_range.start.to_le_bytes());
+        out.extend_from_slice(&self.repeat_range.end.to_le_bytes());
+        // The event count is a 32 bit field in the file format
+        #[expect(clippy::cast_possible_truncation)]
+        out.extend_from_slice(&(n_events as u32).to_le_bytes());
+        for track in &self.melody_tracks {
+            track.write(&mut out);
+        }
+        out.extend_from_slice(&u32::from(self.percussion_track.base.vol).to_le_bytes());
+        for base in tracks {
+            out.extend_from_slice(bytemuck::cast_slice(&base.events));
+            let padding = n_events - base.events.len();
+            out.resize(out.len() + padding * size_of::<Event>(), 0);
+        }
+        out.extend_from_slice(&self.trailing_bytes);
+        out
+    }
+    /// Write the song in PMD format to `writer`
+    ///
+    /// # Errors
+    ///
+    /// - If writing to `writer` fails
+    #[cfg(feature = "std")]
+    pub fn save<W: std::io::Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writer.write_all(&self.to_bytes())
+    }
+}
+
+/// Reads a field with `read`, reporting premature end of file as an error on `field`
+pub(crate) fn read_field<'a, T>(
+    cur: &mut ReadCursor<'a>,
+    field: Field,
+    read: impl FnOnce(&mut ReadCursor<'a>) -> Option<T>,
+) -> Result<T, LoadError> {
+    let offset = cur.pos();
+    read(cur).ok_or(LoadError::PrematureEof { field, offset })
+}
+
+/// Reads a 32 bit field that must fit into 16 bits
+pub(crate) fn read_u16_field(cur: &mut ReadCursor, field: Field) -> Result<u16, LoadError> {
+    let offset = cur.pos();
+    let value = read_field(cur, field, ReadCursor::next_u32_le)?;
+    u16::try_from(value).map_err(|_| LoadError::OutOfRange {
+        field,
+        offset,
+        value,
+    })
+}
+
+fn read_events(
+    cur: &mut ReadCursor,
+    field: Field,
+    n_events: usize,
+) -> Result<Box<[Event]>, LoadError> {
+    let offset = cur.pos();
+    let events: Box<[Event]> = read_field(cur, field, |cur| cur.next_n(n_events))?;
+    for (i, ev) in events.iter().enumerate() {
+        let pan = ev.raw_pan();
+        if pan >= 8 {
+            return Err(LoadError::InvalidPan {
+                field,
+                // Offset of the pan byte, which is the most significant byte of the event
+                offset: offset + i * size_of::<Event>() + 3,
+                value: pan,
+            });
+        }
+    }
+    Ok(events)
+}
+
+/// A field of a PMD file, used to report where loading failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// Uninterpreted header bytes following the magic marker
+    HeaderExtra,
+    /// Milliseconds to wait between events
+    EventWait,
+    /// Start of the repeat range
+    RepeatStart,
+    /// End of the repeat range
+    RepeatEnd,
+    /// Number of events in each track
+    EventCount,
+    /// A field of the melody track at this index of [`Song::melody_tracks`]
+    Melody(u8, MelodyField),
+    /// Volume of the percussion track
+    PercussionVolume,
+    /// Events of the melody track at this index of [`Song::melody_tracks`]
+    MelodyEvents(u8),
+    /// Events of the percussion track
+    PercussionEvents,
+}
+
+/// A field of a [`MelodyTrack`] in a PMD file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MelodyField {
+    /// [`MelodyTrack::octave`]
+    Octave,
+    /// [`MelodyTrack::extra_1`]
+    Extra1,
+    /// [`MelodyTrack::len`]
+    Len,
+    /// Volume of the track
+    Volume,
+    /// [`MelodyTrack::extra_2`]
+    Extra2,
+    /// [`MelodyTrack::waveform`]
+    Waveform,
+    /// [`MelodyTrack::envelope`]
+    Envelope,
+}
+
+impl core::fmt::Display for Field {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Field::HeaderExtra => f.write_str("header"),
+            Field::EventWait => f.write_str("event wait"),
+            Field::RepeatStart => f.write_str("repeat start"),
+            Field::RepeatEnd => f.write_str("repeat end"),
+            Field::EventCount => f.write_str("event count"),
+            Field::Melody(idx, field) => write!(f, "melody track {idx} {field}"),
+            Field::PercussionVolume => f.write_str("percussion track volume"),
+            Field::MelodyEvents(idx) => write!(f, "melody track {idx} events"),
+            Field::PercussionEvents => f.write_str("percussion track events"),
+        }
+    }
+}
+
+impl core::fmt::Display for MelodyField {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            MelodyField::Octave => "octave",
+            MelodyField::Extra1 | MelodyField::Extra2 => "extra bytes",
+            MelodyField::Len => "length",
+            MelodyField::Volume => "volume",
+            MelodyField::Waveform => "waveform",
+            MelodyField::Envelope => "envelope",
         })
     }
 }
@@ -66,16 +288,58 @@ pub enum LoadError {
     /// Invalid magic (not `PMD`)
     InvalidMagic,
     /// End of file was reached prematurely
-    PrematureEof,
+    PrematureEof {
+        /// The field that couldn't be read
+        field: Field,
+        /// Byte offset of the field
+        offset: usize,
+    },
+    /// A field holds a value too large to be represented
+    OutOfRange {
+        /// The offending field
+        field: Field,
+        /// Byte offset of the field
+        offset: usize,
+        /// The value read from the file
+        value: u32,
+    },
+    /// An event holds a pan value outside of the pan table
+    InvalidPan {
+        /// The events of the track the offending event belongs to
+        field: Field,
+        /// Byte offset of the pan value
+        offset: usize,
+        /// The pan value read from the file
+        value: u8,
+    },
 }
 
-impl std::fmt::Display for LoadError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             LoadError::InvalidMagic => f.write_str("Invalid magic (expected PMD)"),
-            LoadError::PrematureEof => f.write_str("End of file reached prematurely"),
+            LoadError::PrematureEof { field, offset } => write!(
+                f,
+                "End of file reached prematurely reading {field} at offset {offset:#X}"
+            ),
+            LoadError::OutOfRange {
+                field,
+                offset,
+                value,
+            } => write!(
+                f,
+                "Value {value} out of range for {field} at offset {offset:#X}"
+            ),
+            LoadError::InvalidPan {
+                field,
+                offset,
+                value,
+            } => write!(
+                f,
+                "Invalid pan value {value} in {field} at offset {offset:#X}"
+            ),
         }
     }
 }
 
-impl std::error::Error for LoadError {}
+impl core::error::Error for LoadError {}
@@ -0,0 +1,225 @@
+use {
+    crate::{Song, TrackId, track::TrackBase},
+    alloc::vec::Vec,
+};
+
+/// How severe a [`Diagnostic`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The song plays, but probably not as intended
+    Warning,
+    /// The song can't be played correctly, and might cause a panic during playback
+    Error,
+}
+
+/// Where in the song a [`Diagnostic`] applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    /// The song as a whole
+    Song,
+    /// A whole track
+    Track(TrackId),
+    /// The event at an index of a track
+    Event(TrackId, usize),
+}
+
+/// A problem found by [`Song::validate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// The song has no events
+    NoEvents,
+    /// The event wait is zero, so every event is processed on a single sample
+    ZeroEventWait,
+    /// The repeat range starts after it ends
+    RepeatRangeInverted,
+    /// The repeat range is empty, so the same event repeats forever
+    RepeatRangeEmpty,
+    /// The repeat range starts or ends past the last event
+    RepeatRangePastEnd {
+        /// Number of events in the song
+        n_events: usize,
+    },
+    /// A track has a different number of events than the percussion track
+    EventCountMismatch {
+        /// Number of events in the percussion track
+        expected: usize,
+        /// Number of events in this track
+        actual: usize,
+    },
+    /// The track volume is outside of `0..=300`
+    VolumeOutOfRange(u16),
+    /// The octave is above 7
+    OctaveOutOfRange(u8),
+    /// The note length is zero, so notes are silent
+    ZeroLength,
+    /// The waveform is all zeroes, so the track is silent
+    SilentWaveform,
+    /// The envelope is all zeroes, so the track is silent
+    SilentEnvelope,
+    /// The event has a pan value outside of the pan table
+    InvalidPan(u8),
+}
+
+impl Problem {
+    /// The severity of this problem
+    #[must_use]
+    pub const fn severity(&self) -> Severity {
+        match self {
+            Self::NoEvents
+            | Self::RepeatRangeInverted
+            | Self::RepeatRangePastEnd { .. }
+            | Self::EventCountMismatch { .. }
+            | Self::OctaveOutOfRange(_)
+            | Self::InvalidPan(_) => Severity::Error,
+            Self::ZeroEventWait
+            | Self::RepeatRangeEmpty
+            | Self::VolumeOutOfRange(_)
+            | Self::ZeroLength
+            | Self::SilentWaveform
+            | Self::SilentEnvelope => Severity::Warning,
+        }
+    }
+}
+
+/// A problem found by [`Song::validate`], along with its location
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Where the problem is
+    pub location: Location,
+    /// What the problem is
+    pub problem: Problem,
+}
+
+impl Diagnostic {
+    /// The severity of the problem
+    #[must_use]
+    pub const fn severity(&self) -> Severity {
+        self.problem.severity()
+    }
+}
+
+impl core::fmt::Display for Location {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Location::Song => f.write_str("song"),
+            Location::Track(track) => write!(f, "{track}"),
+            Location::Event(track, idx) => write!(f, "{track} event {idx}"),
+        }
+    }
+}
+
+impl core::fmt::Display for Problem {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Problem::NoEvents => f.write_str("song has no events"),
+            Problem::ZeroEventWait => f.write_str("event wait is zero"),
+            Problem::RepeatRangeInverted => f.write_str("repeat range starts after it ends"),
+            Problem::RepeatRangeEmpty => f.write_str("repeat range is empty"),
+            Problem::RepeatRangePastEnd { n_events } => {
+                write!(
+                    f,
+                    "repeat range lies past the last event ({n_events} events)"
+                )
+            }
+            Problem::EventCountMismatch { expected, actual } => {
+                write!(f, "has {actual} events, expected {expected}")
+            }
+            Problem::VolumeOutOfRange(vol) => write!(f, "volume {vol} is outside of 0..=300"),
+            Problem::OctaveOutOfRange(oct) => write!(f, "octave {oct} is above 7"),
+            Problem::ZeroLength => f.write_str("note length is zero"),
+            Problem::SilentWaveform => f.write_str("waveform is silent"),
+            Problem::SilentEnvelope => f.write_str("envelope is silent"),
+            Problem::InvalidPan(pan) => write!(f, "invalid pan value {pan}"),
+        }
+    }
+}
+
+impl core::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let severity = match self.severity() {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{severity}: {}: {}", self.location, self.problem)
+    }
+}
+
+impl Song {
+    /// Check the song for problems that don't prevent loading, but make it play badly,
+    /// or cause a panic during playback
+    #[must_use]
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diags = Vec::new();
+        let mut push = |location, problem| diags.push(Diagnostic { location, problem });
+        let n_events = self.percussion_track.base.events.len();
+        if n_events == 0 {
+            push(Location::Song, Problem::NoEvents);
+        }
+        if self.event_wait_ms == 0 {
+            push(Location::Song, Problem::ZeroEventWait);
+        }
+        let range = &self.repeat_range;
+        if range.start > range.end {
+            push(Location::Song, Problem::RepeatRangeInverted);
+        } else if range.start == range.end {
+            push(Location::Song, Problem::RepeatRangeEmpty);
+        }
+        // The player wraps around to the repeat start, so it has to point at an event
+        let start = usize::try_from(range.start).unwrap_or(usize::MAX);
+        let end = usize::try_from(range.end).unwrap_or(usize::MAX);
+        if start >= n_events || end > n_events {
+            push(Location::Song, Problem::RepeatRangePastEnd { n_events });
+        }
+        for (idx, track) in (0..).zip(&self.melody_tracks) {
+            let location = Location::Track(TrackId::Melody(idx));
+            if track.octave > 7 {
+                push(location, Problem::OctaveOutOfRange(track.octave));
+            }
+            if track.len == 0 {
+                push(location, Problem::ZeroLength);
+            }
+            if track.waveform.iter().all(|&s| s == 0) {
+                push(location, Problem::SilentWaveform);
+            }
+            if track.envelope.iter().all(|&v| v == 0) {
+                push(location, Problem::SilentEnvelope);
+            }
+            validate_base(&track.base, TrackId::Melody(idx), n_events, &mut push);
+        }
+        validate_base(
+            &self.percussion_track.base,
+            TrackId::Percussion,
+            n_events,
+            &mut push,
+        );
+        diags
+    }
+}
+
+fn validate_base(
+    base: &TrackBase,
+    track: TrackId,
+    n_events: usize,
+    push: &mut impl FnMut(Location, Problem),
+) {
+    if base.vol > 300 {
+        push(Location::Track(track), Problem::VolumeOutOfRange(base.vol));
+    }
+    if base.events.len() != n_events {
+        push(
+            Location::Track(track),
+            Problem::EventCountMismatch {
+                expected: n_events,
+                actual: base.events.len(),
+            },
+        );
+    }
+    for (idx, ev) in base.events.iter().enumerate() {
+        if ev.raw_pan() >= 8 {
+            push(
+                Location::Event(track, idx),
+                Problem::InvalidPan(ev.raw_pan()),
+            );
+        }
+    }
+}
@@ -1,95 +1,418 @@
 pub use self::{
-    melody::MelodyTrack,
+    drum_kit::{DrumKit, DrumSample, DrumSampleError},
+    melody::{Interpolation, MelodyTrack},
     percussion::{DRUM_SAMPLES, PercussionTrack},
 };
 
-use crate::StereoSample;
+use {
+    self::melody::BandLimitedWaveform,
+    crate::{Sample, StereoSample, math},
+    alloc::boxed::Box,
+};
 
+mod drum_kit;
 mod melody;
 mod percussion;
 
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TrackBase {
     // Seems to be in the range 0..=300
     pub vol: u16,
+    pub events: Box<[Event]>,
+}
+
+/// Playback state of the voices of a track
+///
+/// Every key of a track has its own voice, which restarts when the key is pressed.
+/// This is kept apart from the track itself, so a song can be played by several players at once.
+#[derive(Clone)]
+pub struct Voices {
     vol_left: f32,
     vol_right: f32,
     vol_mix: f32,
+    /// Mix volume of the quieter (odd) percussion keys
+    vol_mix_low: f32,
+    /// Volume of the keys and of the quieter (odd) percussion keys, in hundredths of decibels
+    vol_mb: [i32; 2],
+    /// Volume offset of the pan (see [`Pan::volume_offset`])
+    pan: i16,
     timers: [f64; N_KEYS as usize],
     phases: [f64; N_KEYS as usize],
-    pub events: Box<[Event]>,
+    /// Whether the fixed point state below is used instead of the timers, phases and volumes
+    /// above
+    fixed_point: bool,
+    /// Timers in 32.32 fixed point
+    fixed_timers: [u64; N_KEYS as usize],
+    /// Phases in 32.32 fixed point
+    fixed_phases: [u64; N_KEYS as usize],
+    /// Left and right gains in 16.16 fixed point, of the keys and of the quieter percussion keys
+    fixed_gains: [[u32; 2]; 2],
+    /// How melody waveforms are sampled
+    interpolation: Interpolation,
+    /// Semitones melody keys are shifted by
+    pub(crate) transpose: i16,
+    /// Waveform filtered for [`Interpolation::BandLimited`], computed on demand
+    band_limited: Option<Box<BandLimitedWaveform>>,
+    /// Samples played by percussion keys
+    pub(crate) kit: DrumKit,
 }
 
-impl Default for TrackBase {
+impl Default for Voices {
     fn default() -> Self {
         Self {
-            vol: 0,
             vol_left: 1.0,
             vol_right: 1.0,
             vol_mix: 0.0,
+            vol_mix_low: 0.0,
+            vol_mb: [0; 2],
+            pan: 0,
             timers: Default::default(),
             phases: Default::default(),
-            events: Box::default(),
+            fixed_point: false,
+            fixed_timers: Default::default(),
+            fixed_phases: Default::default(),
+            fixed_gains: [[0; 2]; 2],
+            interpolation: Interpolation::Nearest,
+            transpose: 0,
+            band_limited: None,
+            kit: DrumKit::default(),
         }
     }
 }
 
+impl Voices {
+    /// For each piano key, how much time there's after a keypress left until silence (0.0)
+    ///
+    /// Can be used for example to detect which keys are being held down currently
+    #[must_use]
+    pub fn timers(&self) -> [f64; N_KEYS as usize] {
+        if self.fixed_point {
+            // Precision loss is negligible for timers
+            #[expect(clippy::cast_precision_loss)]
+            {
+                self.fixed_timers.map(|t| t as f64 / FIXED_ONE)
+            }
+        } else {
+            self.timers
+        }
+    }
+    /// How melody waveforms are sampled (ignored by percussion tracks)
+    #[must_use]
+    pub const fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+    /// Set how melody waveforms are sampled
+    pub const fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+    /// Whether the voices are rendered with fixed point math
+    #[must_use]
+    pub const fn fixed_point(&self) -> bool {
+        self.fixed_point
+    }
+    /// Set whether the voices are rendered with fixed point math
+    ///
+    /// Voices in fixed point mode have to be rendered with [`Track::render_next_fixed`].
+    /// Switching modes converts the state of the voices, so playing notes continue.
+    ///
+    /// See [`Player::set_fixed_point`](crate::Player::set_fixed_point) for how the output of
+    /// both modes compares.
+    pub fn set_fixed_point(&mut self, fixed_point: bool) {
+        if fixed_point == self.fixed_point {
+            return;
+        }
+        self.fixed_point = fixed_point;
+        // Float to int casts saturate, and precision loss is negligible for timers and phases
+        #[expect(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        if fixed_point {
+            self.fixed_timers = self.timers.map(|t| (t * FIXED_ONE) as u64);
+            self.fixed_phases = self.phases.map(|p| (p * FIXED_ONE) as u64);
+        } else {
+            self.timers = self.fixed_timers.map(|t| t as f64 / FIXED_ONE);
+            self.phases = self.fixed_phases.map(|p| p as f64 / FIXED_ONE);
+        }
+        self.update_gains();
+    }
+    /// Start the voice of `key`, which lasts for `duration` voice samples
+    fn press(&mut self, key: PianoKey, duration: f64) {
+        let key = usize::from(key);
+        if self.fixed_point {
+            // Float to int casts saturate
+            #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            {
+                self.fixed_timers[key] = (duration * FIXED_ONE) as u64;
+            }
+            self.fixed_phases[key] = 0;
+        } else {
+            self.timers[key] = duration;
+            self.phases[key] = 0.;
+        }
+    }
+    /// Compute the gains of the current mode from the volumes and the pan
+    fn update_gains(&mut self) {
+        let pan = [self.pan.min(0), (-self.pan).min(0)].map(i32::from);
+        if self.fixed_point {
+            self.fixed_gains = self.vol_mb.map(|vol| pan.map(|pan| fixed_gain(vol + pan)));
+        } else {
+            // Volumes are at most `u16::MAX * 8`, so they fit into f32 exactly
+            #[expect(clippy::cast_precision_loss)]
+            let [vol, vol_low] = self.vol_mb.map(|vol| vol as f32);
+            self.vol_mix = math::powf(10.0, vol / 2000.0);
+            self.vol_mix_low = math::powf(10.0, vol_low / 2000.0);
+            self.vol_left = math::powf(10.0, f32::from(self.pan.min(0)) / 2000.0);
+            self.vol_right = math::powf(10.0, f32::from((-self.pan).min(0)) / 2000.0);
+        }
+    }
+    /// Silence all voices, and restore the pan `track` has in effect when reaching `event_idx`
+    ///
+    /// `looped_range` is the repeat range if playback has looped back to its start at least
+    /// once, so the previous pass through it precedes `event_idx`.
+    pub(crate) fn reset(
+        &mut self,
+        track: &TrackBase,
+        event_idx: usize,
+        looped_range: Option<core::ops::Range<usize>>,
+    ) {
+        self.timers = Default::default();
+        self.phases = Default::default();
+        self.fixed_timers = Default::default();
+        self.fixed_phases = Default::default();
+        let len = track.events.len();
+        let events = |range: core::ops::Range<usize>| {
+            track
+                .events
+                .get(range.start..range.end.min(len))
+                .unwrap_or_default()
+        };
+        let (current, earlier) = match looped_range {
+            Some(range) => (events(range.start..event_idx), events(0..range.end)),
+            None => (events(0..event_idx), &[][..]),
+        };
+        let last_pan = current
+            .iter()
+            .rev()
+            .chain(earlier.iter().rev())
+            .find_map(|ev| ev.pan());
+        // A pan of zero is centered, like the initial state
+        self.pan = last_pan.unwrap_or(0);
+        self.update_gains();
+    }
+}
+
+/// One in 32.32 fixed point
+const FIXED_ONE: f64 = 4_294_967_296.0;
+
+/// Gains for attenuations of 0 to 1992 hundredths of decibels in steps of 8, in 1.31 fixed
+/// point
+///
+/// Each step is a factor of `10^(-1/250)`, so the table covers a factor of 10.
+const GAIN_TABLE: [u32; 250] = {
+    const STEP: f64 = 0.990_831_944_892_767_6;
+    let mut table = [0; 250];
+    let mut gain = 1.0;
+    let mut i = 0;
+    while i < table.len() {
+        // The gain is at most 1.0, and the error of truncating it is far below 16.16 precision
+        #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        {
+            table[i] = (gain * 2_147_483_648.0) as u32;
+        }
+        gain *= STEP;
+        i += 1;
+    }
+    table
+};
+
+/// Largest fixed point gain (16.0, or about 24 decibels), which keeps voices from overflowing
+const MAX_FIXED_GAIN: u32 = 16 << 16;
+
+/// The gain of a volume of `mb` hundredths of decibels, in 16.16 fixed point
+///
+/// `mb` is rounded down to a multiple of 8, the step of track volumes and pans. The gain is
+/// rounded to the nearest 16.16 value, and clamped to [`MAX_FIXED_GAIN`].
+fn fixed_gain(mb: i32) -> u32 {
+    let steps = -mb.div_euclid(8);
+    let decades = steps.div_euclid(250);
+    let gain = u64::from(GAIN_TABLE[steps.rem_euclid(250) as usize]);
+    let gain = match decades {
+        // Dividing by 10^15 leaves nothing of a 16.16 gain
+        15.. => 0,
+        // Converted from 1.31 to 16.16, rounding to nearest
+        0.. => {
+            let div = 10u64.pow(decades.unsigned_abs()) << 15;
+            (gain + div / 2) / div
+        }
+        -2.. => (gain * 10u64.pow(decades.unsigned_abs())) >> 15,
+        _ => u64::MAX,
+    };
+    u32::try_from(gain).unwrap_or(u32::MAX).min(MAX_FIXED_GAIN)
+}
+
+/// Apply a 16.16 fixed point `gain` to a sample `s` with `frac_bits` fractional bits
+///
+/// The result is truncated and saturated, like the casts of the floating point path.
+fn apply_fixed_gain(s: i64, gain: u32, frac_bits: u32) -> Sample {
+    let s = s * i64::from(gain) / (1 << (16 + frac_bits));
+    // Clamped to the sample range
+    #[expect(clippy::cast_possible_truncation)]
+    {
+        s.clamp(Sample::MIN.into(), Sample::MAX.into()) as Sample
+    }
+}
+
 /// A track defines how to interpret events and generate sound samples from them.
 ///
 /// There are 3 melody tracks and one drum track.
 pub trait Track {
-    /// How long the note will last after being pressed
-    fn note_duration(&self, key: PianoKey) -> f64;
+    /// How long the note will last after being pressed, when played by `voices`
+    fn note_duration(&self, voices: &Voices, key: PianoKey) -> f64;
+    /// Generates an unquantized sample for a piano key being held down at index `key`
+    ///
+    /// The sample is in the range of [`Sample`], but isn't truncated or clamped to it.
+    fn sample_of_key_f64(&self, voices: &mut Voices, key: PianoKey, samp_phase: f64) -> [f64; 2];
+    /// Generates a sample for a piano key being held down at index `key` with fixed point math
+    ///
+    /// `samp_phase` is in 32.32 fixed point. Melody waveforms are always sampled like
+    /// [`Interpolation::Nearest`].
+    fn sample_of_key_fixed(
+        &self,
+        voices: &mut Voices,
+        key: PianoKey,
+        samp_phase: u64,
+    ) -> StereoSample;
     /// Generates a sample for a piano key being held down at index `key`
-    fn sample_of_key(&mut self, key: PianoKey, samp_phase: f64) -> StereoSample;
+    fn sample_of_key(&self, voices: &mut Voices, key: PianoKey, samp_phase: f64) -> StereoSample {
+        // Authentic output truncates every voice to an integer sample before mixing
+        #[expect(clippy::cast_possible_truncation)]
+        self.sample_of_key_f64(voices, key, samp_phase)
+            .map(|s| s as Sample)
+    }
     /// Returns the data shared between melody and trum tracks
-    fn base(&mut self) -> &mut TrackBase;
+    fn base(&self) -> &TrackBase;
     /// Processes the event at the provided event index in the track's own event data
-    fn do_event_at_idx(&mut self, event_idx: usize) {
-        let event = self.base().events[event_idx];
-        self.do_event(event);
+    ///
+    /// Indices past the end of the track are empty events.
+    fn do_event_at_idx(&self, voices: &mut Voices, event_idx: usize) {
+        let event = self.base().events.get(event_idx);
+        self.do_event(voices, event.copied().unwrap_or(Event::from_bits(0)));
     }
-    /// Processes the provided event
-    fn do_event(&mut self, event: Event) {
+    /// Processes the provided event, starting the voices of the keys it presses
+    fn do_event(&self, voices: &mut Voices, event: Event) {
         for key in piano_keys() {
             if event.key_down(key) {
-                self.base().timers[usize::from(key)] = self.note_duration(key);
-                self.base().phases[usize::from(key)] = 0.;
+                voices.press(key, self.note_duration(voices, key));
             }
         }
-        let vol = f32::from((i16::try_from(self.base().vol).unwrap() - 300) * 8);
-        self.base().vol_mix = 10.0f32.powf(vol / 2000.0);
+        voices.vol_mb[0] = (i32::from(self.base().vol) - 300) * 8;
         if let Some(pan) = event.pan() {
-            self.base().vol_left = 10.0f32.powf(f32::from(pan.min(0)) / 2000.0);
-            self.base().vol_right = 10.0f32.powf(f32::from((-pan).min(0)) / 2000.0);
+            voices.pan = pan;
         }
-        self.post_event();
+        self.post_event(voices);
+        voices.update_gains();
     }
     /// Some tracks have to do some post-event handling
-    fn post_event(&mut self) {}
-    /// Render the next stereo sample according to the internal state of the track
-    fn render_next(&mut self, [out_l, out_r]: &mut StereoSample, samp_phase: f64) {
+    fn post_event(&self, _voices: &mut Voices) {}
+    /// Render the next stereo sample of `voices`, and advance them
+    fn render_next(&self, voices: &mut Voices, [out_l, out_r]: &mut StereoSample, samp_phase: f64) {
         for key in piano_keys() {
-            if self.base().timers[usize::from(key)] <= 0.0 {
+            if voices.timers[usize::from(key)] <= 0.0 {
                 continue;
             }
-            self.base().timers[usize::from(key)] -= samp_phase;
+            voices.timers[usize::from(key)] -= samp_phase;
 
-            let [l, r] = self.sample_of_key(key, samp_phase);
+            let [l, r] = self.sample_of_key(voices, key, samp_phase);
             *out_l = out_l.saturating_add(l);
             *out_r = out_r.saturating_add(r);
         }
     }
-    /// For each piano key, how much time there's after a keypress left until silence (0.0)
+    /// Like [`Track::render_next`], but for voices in fixed point mode
+    /// (see [`Voices::set_fixed_point`])
     ///
-    /// Can be used for example to detect which keys are being held down currently
-    fn timers(&mut self) -> [f64; N_KEYS as usize] {
-        self.base().timers
+    /// `samp_phase` is in 32.32 fixed point.
+    fn render_next_fixed(
+        &self,
+        voices: &mut Voices,
+        [out_l, out_r]: &mut StereoSample,
+        samp_phase: u64,
+    ) {
+        for key in piano_keys() {
+            let timer = &mut voices.fixed_timers[usize::from(key)];
+            if *timer == 0 {
+                continue;
+            }
+            *timer = timer.saturating_sub(samp_phase);
+
+            let [l, r] = self.sample_of_key_fixed(voices, key, samp_phase);
+            *out_l = out_l.saturating_add(l);
+            *out_r = out_r.saturating_add(r);
+        }
+    }
+    /// Like [`Track::render_next`], but mixes the voices without quantizing or clipping them
+    fn render_next_f32(&self, voices: &mut Voices, [out_l, out_r]: &mut [f32; 2], samp_phase: f64) {
+        for key in piano_keys() {
+            if voices.timers[usize::from(key)] <= 0.0 {
+                continue;
+            }
+            voices.timers[usize::from(key)] -= samp_phase;
+
+            let [l, r] = self.sample_of_key_f64(voices, key, samp_phase);
+            // The mix bus is f32, which has plenty of precision for the sample range
+            #[expect(clippy::cast_possible_truncation)]
+            {
+                *out_l += l as f32;
+                *out_r += r as f32;
+            }
+        }
+    }
+}
+
+/// Identifies one of the tracks of a [`Song`](crate::Song)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrackId {
+    /// The melody track at this index of [`Song::melody_tracks`](crate::Song::melody_tracks)
+    Melody(u8),
+    /// The percussion track
+    Percussion,
+}
+
+impl TrackId {
+    /// All tracks of a song, with the percussion track last
+    pub const ALL: [Self; 4] = [
+        Self::Melody(0),
+        Self::Melody(1),
+        Self::Melody(2),
+        Self::Percussion,
+    ];
+    /// Index of this track in [`TrackId::ALL`]
+    #[must_use]
+    pub const fn index(self) -> usize {
+        match self {
+            Self::Melody(idx) => idx as usize,
+            Self::Percussion => 3,
+        }
+    }
+}
+
+impl core::fmt::Display for TrackId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TrackId::Melody(idx) => write!(f, "melody track {idx}"),
+            TrackId::Percussion => f.write_str("percussion track"),
+        }
     }
 }
 
 /// An event consisting of piano key down states and optional pan value
 #[repr(transparent)]
 #[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Event(u32);
 
 impl Event {
@@ -106,11 +429,49 @@ impl Event {
     pub const fn set_key_up(&mut self, key: PianoKey) {
         self.0 &= !(1 << key);
     }
+    /// Returns an iterator over the piano keys that are down in this event
+    pub fn keys_down(self) -> impl Iterator<Item = PianoKey> {
+        piano_keys().filter(move |&key| self.key_down(key))
+    }
     /// Return the pan value (if any) of this event
+    ///
+    /// This is the volume offset applied by [`Pan::volume_offset`].
+    /// Out of range pan values are treated as no pan.
     #[must_use]
     pub fn pan(self) -> Option<i16> {
-        let pan_table = [2560, 1600, 760, 320, 0, -320, -760, -1640];
-        (self.0 & 0xff00_0000 != 0).then(|| pan_table[(self.0 >> 24) as usize])
+        self.pan_pos().map(Pan::volume_offset)
+    }
+    /// Return the pan position (if any) of this event
+    ///
+    /// Out of range pan values are treated as no pan.
+    #[must_use]
+    pub const fn pan_pos(self) -> Option<Pan> {
+        Pan::from_raw(self.raw_pan())
+    }
+    /// Set the pan position of this event
+    pub const fn set_pan(&mut self, pan: Pan) {
+        self.0 = (self.0 & !PAN_MASK) | ((pan as u32) << 24);
+    }
+    /// Remove the pan value from this event, so that it doesn't change the pan
+    pub const fn clear_pan(&mut self) {
+        self.0 &= !PAN_MASK;
+    }
+    /// The raw pan byte, which is an index into the pan table (0 means no pan)
+    #[must_use]
+    pub const fn raw_pan(self) -> u8 {
+        (self.0 >> 24) as u8
+    }
+    /// Construct an event from its raw bit representation
+    ///
+    /// The lower 24 bits are the piano key down states, the upper 8 bits the raw pan value.
+    #[must_use]
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+    /// The raw bit representation of this event (see [`Event::from_bits`])
+    #[must_use]
+    pub const fn bits(self) -> u32 {
+        self.0
     }
     /// Construct an event from an array of piano key down states
     #[must_use]
@@ -125,6 +486,79 @@ impl Event {
     }
 }
 
+const PAN_MASK: u32 = 0xff00_0000;
+
+/// Pan position of an [`Event`]
+///
+/// Positive volume offsets attenuate the right channel, negative ones the left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum Pan {
+    /// Leftmost position
+    Left3 = 1,
+    /// Left
+    Left2,
+    /// Slightly left
+    Left1,
+    /// Center
+    Center,
+    /// Slightly right
+    Right1,
+    /// Right
+    Right2,
+    /// Rightmost position
+    Right3,
+}
+
+impl Pan {
+    /// All pan positions, from left to right
+    pub const ALL: [Self; 7] = [
+        Self::Left3,
+        Self::Left2,
+        Self::Left1,
+        Self::Center,
+        Self::Right1,
+        Self::Right2,
+        Self::Right3,
+    ];
+    /// Convert a raw pan byte (as stored in an event) into a pan position
+    ///
+    /// Returns `None` for 0 (no pan) and out of range values.
+    #[must_use]
+    pub const fn from_raw(raw: u8) -> Option<Self> {
+        match raw {
+            1..=7 => Some(Self::ALL[raw as usize - 1]),
+            _ => None,
+        }
+    }
+    /// The raw pan byte of this pan position
+    #[must_use]
+    pub const fn to_raw(self) -> u8 {
+        self as u8
+    }
+    /// The volume offset (in hundredths of decibels) applied to the attenuated channel
+    #[must_use]
+    pub const fn volume_offset(self) -> i16 {
+        const PAN_TABLE: [i16; 8] = [2560, 1600, 760, 320, 0, -320, -760, -1640];
+        PAN_TABLE[self as usize]
+    }
+}
+
+impl From<Pan> for u8 {
+    fn from(pan: Pan) -> Self {
+        pan.to_raw()
+    }
+}
+
+impl TryFrom<u8> for Pan {
+    type Error = u8;
+
+    fn try_from(raw: u8) -> Result<Self, Self::Error> {
+        Self::from_raw(raw).ok_or(raw)
+    }
+}
+
 /// Number of piano keys
 pub const N_KEYS: PianoKey = 24;
 
@@ -133,6 +567,6 @@ pub type PianoKey = u8;
 
 /// Returns a range of all piano keys
 #[must_use]
-pub const fn piano_keys() -> std::ops::Range<PianoKey> {
+pub const fn piano_keys() -> core::ops::Range<PianoKey> {
     0..N_KEYS
 }
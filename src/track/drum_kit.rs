@@ -0,0 +1,271 @@
+use {
+    crate::{
+        read_cursor::ReadCursor,
+        track::{N_KEYS, PianoKey, percussion::DRUM_SAMPLES},
+    },
+    alloc::sync::Arc,
+};
+
+/// Rate of the built-in drum samples, which is also the rate voices are rendered at
+const RAW_RATE: u32 = 22_050;
+
+/// A single percussion sample, played by a percussion key
+#[derive(Clone)]
+pub struct DrumSample {
+    data: SampleData,
+    rate: u32,
+}
+
+#[derive(Clone)]
+enum SampleData {
+    /// Unsigned 8 bit samples, centered at 128
+    Builtin(&'static [u8]),
+    /// Signed 16 bit samples
+    Pcm(Arc<[i16]>),
+}
+
+impl DrumSample {
+    /// Create a sample from unsigned 8 bit mono samples at 22050 Hz
+    ///
+    /// This is the format of the built-in samples (see [`DRUM_SAMPLES`]).
+    #[must_use]
+    pub fn from_raw(data: &[u8]) -> Self {
+        let samples = data.iter().map(|&s| (i16::from(s) - 128) << 8).collect();
+        Self::from_pcm(samples, RAW_RATE)
+    }
+    /// Create a sample from signed 16 bit mono samples at `rate` Hz
+    ///
+    /// A rate of 0 is treated as 1 Hz.
+    #[must_use]
+    pub fn from_pcm(samples: Arc<[i16]>, rate: u32) -> Self {
+        Self {
+            data: SampleData::Pcm(samples),
+            rate: rate.max(1),
+        }
+    }
+    /// Load a sample from a WAVE file with 8 or 16 bit integer samples
+    ///
+    /// Channels are mixed down to mono.
+    ///
+    /// # Errors
+    ///
+    /// - If the file is not a RIFF WAVE file
+    /// - If the samples are not 8 or 16 bit PCM
+    /// - If the format or the data chunk is missing, or the file is too short
+    pub fn from_wav(data: &[u8]) -> Result<Self, DrumSampleError> {
+        let mut cur = ReadCursor::new(data);
+        if cur.next_bytes() != Some(b"RIFF") {
+            return Err(DrumSampleError::InvalidHeader);
+        }
+        cur.next_u32_le().ok_or(DrumSampleError::InvalidHeader)?;
+        if cur.next_bytes() != Some(b"WAVE") {
+            return Err(DrumSampleError::InvalidHeader);
+        }
+        let mut format = None;
+        loop {
+            let offset = cur.pos();
+            let eof = || DrumSampleError::PrematureEof { offset };
+            let Some(id) = cur.next_bytes::<4>() else {
+                return Err(DrumSampleError::MissingChunk);
+            };
+            let size = cur.next_u32_le().ok_or_else(eof)? as usize;
+            let body = cur.next_slice(size).ok_or_else(eof)?;
+            // Chunks are padded to an even size
+            if size % 2 == 1 {
+                cur.next_u8();
+            }
+            match id {
+                b"fmt " => format = Some(WavFormat::parse(body).ok_or_else(eof)??),
+                b"data" => {
+                    let format = format.ok_or(DrumSampleError::MissingChunk)?;
+                    return Ok(Self::from_pcm(format.mono_samples(body), format.rate));
+                }
+                _ => {}
+            }
+        }
+    }
+    /// Number of sample points
+    #[must_use]
+    pub fn len(&self) -> usize {
+        match &self.data {
+            SampleData::Builtin(data) => data.len(),
+            SampleData::Pcm(data) => data.len(),
+        }
+    }
+    /// Whether the sample has no sample points
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Sample rate in Hz
+    #[must_use]
+    pub const fn rate(&self) -> u32 {
+        self.rate
+    }
+    /// How long the sample plays, in voice samples (at 22050 Hz)
+    pub(crate) fn voice_len(&self) -> u64 {
+        self.len() as u64 * u64::from(RAW_RATE) / u64::from(self.rate)
+    }
+    /// How far playback advances through the sample for each voice sample
+    pub(crate) fn step(&self) -> f64 {
+        f64::from(self.rate) / f64::from(RAW_RATE)
+    }
+    /// How far playback advances through the sample for a 32.32 fixed point voice step
+    /// `samp_phase`, in 32.32 fixed point
+    pub(crate) fn fixed_step(&self, samp_phase: u64) -> u64 {
+        if self.rate == RAW_RATE {
+            return samp_phase;
+        }
+        let step = u128::from(samp_phase) * u128::from(self.rate) / u128::from(RAW_RATE);
+        u64::try_from(step).unwrap_or(u64::MAX)
+    }
+    /// The sample point at `idx`
+    pub(crate) fn point(&self, idx: usize) -> i16 {
+        match &self.data {
+            SampleData::Builtin(data) => (i16::from(data[idx]) - 128) << 8,
+            SampleData::Pcm(data) => data[idx],
+        }
+    }
+}
+
+struct WavFormat {
+    channels: u16,
+    rate: u32,
+    bits: u16,
+}
+
+impl WavFormat {
+    /// The format stored in the body of a format chunk, if it is 8 or 16 bit PCM
+    fn parse(body: &[u8]) -> Option<Result<Self, DrumSampleError>> {
+        let mut cur = ReadCursor::new(body);
+        let tag = cur.next_u16_le()?;
+        let channels = cur.next_u16_le()?;
+        let rate = cur.next_u32_le()?;
+        // Byte rate and block alignment follow from the rest
+        cur.next_bytes::<6>()?;
+        let bits = cur.next_u16_le()?;
+        // PCM, or the extensible format (which is assumed to hold PCM)
+        let supported = matches!(tag, 1 | 0xFFFE) && matches!(bits, 8 | 16) && channels > 0;
+        Some(if supported {
+            Ok(Self {
+                channels,
+                rate,
+                bits,
+            })
+        } else {
+            Err(DrumSampleError::UnsupportedFormat)
+        })
+    }
+    /// The samples of a data chunk with this format, mixed down to mono
+    fn mono_samples(&self, data: &[u8]) -> Arc<[i16]> {
+        let frame_len = usize::from(self.channels) * usize::from(self.bits / 8);
+        data.chunks_exact(frame_len)
+            .map(|frame| {
+                let sum: i32 = if self.bits == 8 {
+                    frame.iter().map(|&s| (i32::from(s) - 128) << 8).sum()
+                } else {
+                    frame
+                        .as_chunks::<2>()
+                        .0
+                        .iter()
+                        .map(|&s| i32::from(i16::from_le_bytes(s)))
+                        .sum()
+                };
+                // The average of 16 bit samples fits into 16 bits
+                #[expect(clippy::cast_possible_truncation)]
+                {
+                    (sum / i32::from(self.channels)) as i16
+                }
+            })
+            .collect()
+    }
+}
+
+/// Error that can happen when loading a [`DrumSample`]
+#[derive(Debug)]
+pub enum DrumSampleError {
+    /// Not a RIFF WAVE file
+    InvalidHeader,
+    /// The samples are not 8 or 16 bit PCM
+    UnsupportedFormat,
+    /// The format or the data chunk is missing
+    MissingChunk,
+    /// End of file was reached prematurely
+    PrematureEof {
+        /// Byte offset of the chunk that couldn't be read
+        offset: usize,
+    },
+}
+
+impl core::fmt::Display for DrumSampleError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DrumSampleError::InvalidHeader => f.write_str("Invalid header (expected RIFF WAVE)"),
+            DrumSampleError::UnsupportedFormat => {
+                f.write_str("Unsupported sample format (expected 8 or 16 bit PCM)")
+            }
+            DrumSampleError::MissingChunk => f.write_str("Missing format or data chunk"),
+            DrumSampleError::PrematureEof { offset } => {
+                write!(f, "End of file reached prematurely at offset {offset:#X}")
+            }
+        }
+    }
+}
+
+impl core::error::Error for DrumSampleError {}
+
+/// The samples played by the keys of a [`PercussionTrack`](crate::PercussionTrack)
+///
+/// The default kit plays the built-in samples of [`DRUM_SAMPLES`]. Other kits are set with
+/// [`Player::set_drum_kit`](crate::Player::set_drum_kit).
+///
+/// Kits belong to a player rather than a percussion track, as PMD files have no place to store
+/// them. There is no file format for a whole kit either: each sample is loaded on its own with
+/// [`DrumSample::from_raw`] or [`DrumSample::from_wav`], and then put into a kit with
+/// [`DrumKit::from_samples`] or [`DrumKit::set_sample`].
+#[derive(Clone)]
+pub struct DrumKit {
+    samples: [DrumSample; N_KEYS as usize],
+}
+
+impl core::fmt::Debug for DrumKit {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DrumKit").finish_non_exhaustive()
+    }
+}
+
+impl Default for DrumKit {
+    fn default() -> Self {
+        Self {
+            samples: DRUM_SAMPLES.map(|data| DrumSample {
+                data: SampleData::Builtin(data),
+                rate: RAW_RATE,
+            }),
+        }
+    }
+}
+
+impl DrumKit {
+    /// Create a kit playing `samples`, one for each key
+    #[must_use]
+    pub const fn from_samples(samples: [DrumSample; N_KEYS as usize]) -> Self {
+        Self { samples }
+    }
+    /// The sample played by `key`
+    ///
+    /// # Panics
+    ///
+    /// - If `key` is not below [`N_KEYS`]
+    #[must_use]
+    pub fn sample(&self, key: PianoKey) -> &DrumSample {
+        &self.samples[usize::from(key)]
+    }
+    /// Make `key` play `sample`
+    ///
+    /// # Panics
+    ///
+    /// - If `key` is not below [`N_KEYS`]
+    pub fn set_sample(&mut self, key: PianoKey, sample: DrumSample) {
+        self.samples[usize::from(key)] = sample;
+    }
+}
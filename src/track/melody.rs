@@ -1,23 +1,118 @@
-use crate::{
-    Sample, StereoSample,
-    read_cursor::ReadCursor,
-    song::LoadError,
-    track::{PianoKey, Track, TrackBase},
+use {
+    crate::{
+        StereoSample, math,
+        read_cursor::ReadCursor,
+        song::{Field, LoadError, MelodyField, read_field, read_u16_field},
+        track::{N_KEYS, PianoKey, Track, TrackBase, Voices, apply_fixed_gain},
+    },
+    alloc::{boxed::Box, vec::Vec},
 };
 
 /// A melody track based on a waveform and envelope
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MelodyTrack {
     /// Track data common to melody/percussion tracks
     pub base: TrackBase,
     /// The waveform, or in other words, the instrument we're playing
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_array"))]
     pub waveform: [i8; 256],
     /// The envelope (volume variation over time) of the waveform
     /// Seems to be in the range of 0..128
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_array"))]
     pub envelope: [u8; 64],
     /// Octave shift applied when playing the instrument
     pub octave: u8,
+    /// Uninterpreted bytes following `octave`, preserved for saving
+    pub extra_1: [u8; 3],
     /// How long a note "holds" after being hit
     pub len: u16,
+    /// Uninterpreted bytes following the volume, preserved for saving
+    pub extra_2: [u8; 8],
+}
+
+/// How the waveform of a [`MelodyTrack`] is sampled between its points
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Interpolation {
+    /// Use the nearest preceding point, like the original player
+    #[default]
+    Nearest,
+    /// Linear interpolation between neighboring points
+    Linear,
+    /// Cubic Hermite (Catmull-Rom) interpolation over 4 points
+    Cubic,
+    /// Cubic interpolation of a copy of the waveform with the harmonics removed that would
+    /// alias at the current octave, transpose and sample rate
+    BandLimited,
+}
+
+/// A waveform with harmonics above the Nyquist frequency removed
+#[derive(Clone)]
+pub(crate) struct BandLimitedWaveform {
+    /// The waveform this was computed from
+    source: [i8; 256],
+    /// How far the highest key advances through the waveform per sample
+    max_step: f64,
+    table: [f32; 256],
+}
+
+impl BandLimitedWaveform {
+    fn new(source: [i8; 256], max_step: f64) -> Self {
+        // Harmonic `h` completes `h * max_step / 256` cycles per sample, and must stay below
+        // the Nyquist frequency of half a cycle
+        let max_harmonic = math::floor(128.0 / max_step);
+        let mut table = source.map(f32::from);
+        if max_harmonic < 128.0 {
+            // Bounded to 1..128
+            #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let max_harmonic = max_harmonic.max(1.0) as u32;
+            let angle = |i: u32| core::f64::consts::TAU * f64::from(i % 256) / 256.0;
+            let mut out = [0.0; 256];
+            for h in 0..=max_harmonic {
+                let (mut re, mut im) = (0.0, 0.0);
+                for (n, &s) in (0..).zip(&source) {
+                    let (sin, cos) = math::sincos(angle(h * n));
+                    re += f64::from(s) * cos;
+                    im += f64::from(s) * sin;
+                }
+                // Lanczos sigma factors reduce the ringing caused by truncating the spectrum.
+                // Harmonics above zero are doubled to account for their negative counterparts.
+                let sigma = if h == 0 {
+                    1.0
+                } else {
+                    let x = core::f64::consts::PI * f64::from(h) / f64::from(max_harmonic + 1);
+                    2.0 * math::sin(x) / x
+                };
+                for (n, out) in (0..).zip(&mut out) {
+                    let (sin, cos) = math::sincos(angle(h * n));
+                    *out += sigma * math::fma(re, cos, im * sin) / 256.0;
+                }
+            }
+            // The table only needs to be as precise as the mix bus
+            #[expect(clippy::cast_possible_truncation)]
+            {
+                table = out.map(|s| s as f32);
+            }
+        }
+        Self {
+            source,
+            max_step,
+            table,
+        }
+    }
+}
+
+const FREQ_TABLE: [u16; 12] = [
+    1551, 1652, 1747, 1848, 1955, 2074, 2205, 2324, 2461, 2616, 2770, 2938,
+];
+
+/// Cubic Hermite (Catmull-Rom) interpolation at `t` between `p1` and `p2`
+fn cubic([p0, p1, p2, p3]: [f32; 4], t: f32) -> f32 {
+    let a = math::fmaf(1.5, p1 - p2, 0.5 * (p3 - p0));
+    let b = math::fmaf(0.5, -p3, math::fmaf(2.5, -p1, math::fmaf(2.0, p2, p0)));
+    let c = 0.5 * (p2 - p0);
+    math::fmaf(math::fmaf(math::fmaf(a, t, b), t, c), t, p1)
 }
 
 impl Default for MelodyTrack {
@@ -27,84 +122,181 @@ impl Default for MelodyTrack {
             waveform: [0; _],
             envelope: [0; _],
             octave: 0,
+            extra_1: [0; _],
             len: 0,
+            extra_2: [0; _],
         }
     }
 }
 
 impl MelodyTrack {
-    pub(crate) fn read(&mut self, cur: &mut ReadCursor) -> Result<(), LoadError> {
-        self.octave = cur.next_u8().ok_or(LoadError::PrematureEof)?;
-        cur.skip(3);
-        self.len = cur
-            .next_u32_le()
-            .ok_or(LoadError::PrematureEof)?
-            .try_into()
-            .unwrap();
-        self.base.vol = cur
-            .next_u32_le()
-            .ok_or(LoadError::PrematureEof)?
-            .try_into()
-            .unwrap();
-        cur.skip(8);
-        self.waveform =
-            *bytemuck::cast_ref(cur.next_bytes::<256>().ok_or(LoadError::PrematureEof)?);
-        self.envelope = *cur.next_bytes().ok_or(LoadError::PrematureEof)?;
+    pub(crate) fn read(&mut self, cur: &mut ReadCursor, idx: u8) -> Result<(), LoadError> {
+        let field = |field| Field::Melody(idx, field);
+        self.octave = read_field(cur, field(MelodyField::Octave), ReadCursor::next_u8)?;
+        self.extra_1 = *read_field(cur, field(MelodyField::Extra1), ReadCursor::next_bytes)?;
+        self.len = read_u16_field(cur, field(MelodyField::Len))?;
+        self.base.vol = read_u16_field(cur, field(MelodyField::Volume))?;
+        self.extra_2 = *read_field(cur, field(MelodyField::Extra2), ReadCursor::next_bytes)?;
+        self.waveform = bytemuck::cast(*read_field(
+            cur,
+            field(MelodyField::Waveform),
+            ReadCursor::next_bytes::<256>,
+        )?);
+        self.envelope = *read_field(cur, field(MelodyField::Envelope), ReadCursor::next_bytes)?;
         Ok(())
     }
+    /// How far the phase of a voice advances per sample, for a key `semitone` semitones above
+    /// the lowest key
+    ///
+    /// Keys outside of the piano key range continue into the neighboring octaves.
+    fn phase_step(&self, semitone: i32, samp_phase: f64) -> f64 {
+        let octave = i32::from(self.octave) + semitone.div_euclid(12);
+        let note = semitone.rem_euclid(12) as usize;
+        (math::powi(2.0, octave) * (f64::from(FREQ_TABLE[note]) / 16.0)) * samp_phase
+    }
+    /// Like [`MelodyTrack::phase_step`], but with 32.32 fixed point phases
+    ///
+    /// Only the lower bits of the phase select the waveform point, so bits shifted out at the
+    /// top don't matter.
+    fn fixed_phase_step(&self, semitone: i32, samp_phase: u64) -> u64 {
+        let octave = i32::from(self.octave) + semitone.div_euclid(12);
+        let note = semitone.rem_euclid(12) as usize;
+        let step = u64::from(FREQ_TABLE[note]) * samp_phase;
+        // The table holds 16 times the step of octave 0
+        let shift = octave - 4;
+        if shift >= 0 {
+            step.checked_shl(shift.unsigned_abs()).unwrap_or(0)
+        } else {
+            step.checked_shr(shift.unsigned_abs()).unwrap_or(0)
+        }
+    }
+    /// Index into the envelope for a voice with `timer` voice samples left
+    fn envelope_idx(&self, timer: usize) -> usize {
+        let len = usize::from(self.len);
+        ((64 * len.saturating_sub(timer)).checked_div(len))
+            .unwrap_or(0)
+            .min(63)
+    }
+    /// Sample the waveform at position `pos` according to the interpolation of `voices`
+    fn interpolated_sample(&self, voices: &mut Voices, pos: f64, samp_phase: f64) -> f32 {
+        // The position is never negative, and we want its integer part wrapped to the
+        // waveform. Positions too large for usize saturate, which only happens at octaves far
+        // above audible frequencies.
+        #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let idx = pos as usize & 0xff;
+        // Only the fractional part is left, which fits into f32
+        #[expect(clippy::cast_possible_truncation)]
+        let t = math::fract(pos) as f32;
+        let points = |wave: &dyn Fn(usize) -> f32| {
+            [
+                wave(idx.wrapping_sub(1) & 0xff),
+                wave(idx),
+                wave((idx + 1) & 0xff),
+                wave((idx + 2) & 0xff),
+            ]
+        };
+        match voices.interpolation {
+            Interpolation::Nearest => f32::from(self.waveform[idx]),
+            Interpolation::Linear => {
+                let [_, p1, p2, _] = points(&|i| f32::from(self.waveform[i]));
+                math::fmaf(t, p2 - p1, p1)
+            }
+            Interpolation::Cubic => cubic(points(&|i| f32::from(self.waveform[i])), t),
+            Interpolation::BandLimited => {
+                let top_key = i32::from(N_KEYS - 1) + i32::from(voices.transpose);
+                let max_step = self.phase_step(top_key, samp_phase) / 256.0;
+                let bl = match &voices.band_limited {
+                    Some(bl) if bl.max_step.to_bits() == max_step.to_bits() => bl,
+                    _ => voices
+                        .band_limited
+                        .insert(Box::new(BandLimitedWaveform::new(self.waveform, max_step))),
+                };
+                cubic(points(&|i| bl.table[i]), t)
+            }
+        }
+    }
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        out.push(self.octave);
+        out.extend_from_slice(&self.extra_1);
+        out.extend_from_slice(&u32::from(self.len).to_le_bytes());
+        out.extend_from_slice(&u32::from(self.base.vol).to_le_bytes());
+        out.extend_from_slice(&self.extra_2);
+        out.extend_from_slice(bytemuck::cast_slice(&self.waveform));
+        out.extend_from_slice(&self.envelope);
+    }
 }
 
 impl Track for MelodyTrack {
-    fn note_duration(&self, _key: PianoKey) -> f64 {
+    fn note_duration(&self, _voices: &Voices, _key: PianoKey) -> f64 {
         f64::from(self.len)
     }
-    fn sample_of_key(&mut self, key: PianoKey, samp_phase: f64) -> StereoSample {
+    fn sample_of_key_f64(&self, voices: &mut Voices, key: PianoKey, samp_phase: f64) -> [f64; 2] {
+        let semitone = i32::from(key) + i32::from(voices.transpose);
         let key = usize::from(key);
         // If the timer is below 0 due to whatever reason, clamp it back to 0 for sanity's sake.
-        if self.base.timers[key] < 0.0 {
-            self.base.timers[key] = 0.0;
+        if voices.timers[key] < 0.0 {
+            voices.timers[key] = 0.0;
         }
         // Since we use the timer as an index here, truncation is expected.
         // We ignore any fractional part.
         // Also, we expect the timer to remain positive at all times, so there shouldn't be
         // any sign loss
         #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-        let mut idx = (64 * ((self.len as usize).saturating_sub(self.base.timers[key] as usize)))
-            .checked_div(self.len as usize)
-            .unwrap_or(0);
-        if idx >= 64 {
-            idx = 63;
-        }
+        let idx = self.envelope_idx(voices.timers[key] as usize);
         let envelope = 2 * i16::from(self.envelope[idx]);
-        let oct_shift: u8 = 1 << self.octave;
-        let freq_table = [
-            1551., 1652., 1747., 1848., 1955., 2074., 2205., 2324., 2461., 2616., 2770., 2938.,
-        ];
-        let phase = (f64::from(oct_shift)
-            * (if key < 12 {
-                freq_table[key] / 16.0
-            } else {
-                freq_table[key - 12] / 8.0
-            }))
-            * samp_phase;
-        self.base.phases[key] += phase;
-        // We intentionally convert the phase into an index here, so truncation is expected.
-        // Moreover, we assume that phase is never negative, so no sign loss can occur.
-        #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-        let tp = self.base.phases[key] as usize / 256;
-        let s0 = i16::from(self.waveform[tp & 0xff]);
-        let s = s0 * envelope;
+        voices.phases[key] += self.phase_step(semitone, samp_phase);
+        let s = if voices.interpolation == Interpolation::Nearest {
+            // We intentionally convert the phase into an index here, so truncation is expected.
+            // Moreover, we assume that phase is never negative, so no sign loss can occur.
+            #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let tp = voices.phases[key] as usize / 256;
+            let s0 = i32::from(self.waveform[tp & 0xff]);
+            // At most 128 * 510 in magnitude, which an f32 represents exactly
+            #[expect(clippy::cast_precision_loss)]
+            {
+                (s0 * i32::from(envelope)) as f32
+            }
+        } else {
+            let pos = voices.phases[key] / 256.0;
+            self.interpolated_sample(voices, pos, samp_phase) * f32::from(envelope)
+        };
 
-        // We are converting floating point samples to integer samples.
-        // There really isn't anything we can do about the truncation.
-        #[expect(clippy::cast_possible_truncation)]
         [
-            (f32::from(s) * self.base.vol_mix * self.base.vol_left) as Sample,
-            (f32::from(s) * self.base.vol_mix * self.base.vol_right) as Sample,
+            f64::from(s * voices.vol_mix * voices.vol_left),
+            f64::from(s * voices.vol_mix * voices.vol_right),
         ]
     }
+    fn sample_of_key_fixed(
+        &self,
+        voices: &mut Voices,
+        key: PianoKey,
+        samp_phase: u64,
+    ) -> StereoSample {
+        let semitone = i32::from(key) + i32::from(voices.transpose);
+        let key = usize::from(key);
+        let idx = self.envelope_idx((voices.fixed_timers[key] >> 32) as usize);
+        let envelope = 2 * i64::from(self.envelope[idx]);
+        let phase = &mut voices.fixed_phases[key];
+        *phase = phase.wrapping_add(self.fixed_phase_step(semitone, samp_phase));
+        // The waveform point is the integer part of the phase divided by 256, wrapped to the
+        // 256 points of the waveform
+        #[expect(clippy::cast_possible_truncation)]
+        let point = (*phase >> 40) as u8;
+        let s = i64::from(self.waveform[usize::from(point)]) * envelope;
+        voices.fixed_gains[0].map(|gain| apply_fixed_gain(s, gain, 0))
+    }
+    fn post_event(&self, voices: &mut Voices) {
+        // Waveforms can be edited during playback
+        if voices
+            .band_limited
+            .as_ref()
+            .is_some_and(|bl| bl.source != self.waveform)
+        {
+            voices.band_limited = None;
+        }
+    }
 
-    fn base(&mut self) -> &mut TrackBase {
-        &mut self.base
+    fn base(&self) -> &TrackBase {
+        &self.base
     }
 }
@@ -1,60 +1,81 @@
 use crate::{
-    Sample, StereoSample,
-    track::{N_KEYS, PianoKey, Track, TrackBase},
+    StereoSample, math,
+    track::{N_KEYS, PianoKey, Track, TrackBase, Voices, apply_fixed_gain},
 };
 
 /// Percussion track
-#[derive(Default)]
+///
+/// The samples played by its keys come from the [`DrumKit`](crate::DrumKit) of the player.
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PercussionTrack {
     /// The base track data common to melody/percussion tracks
     pub base: TrackBase,
-    vol_mix_low: f32,
 }
 
 impl Track for PercussionTrack {
-    fn note_duration(&self, key: PianoKey) -> f64 {
-        // Percussion samples are short enough to fit into f32 without problem.
+    fn note_duration(&self, voices: &Voices, key: PianoKey) -> f64 {
+        // Percussion samples are short enough to fit into f64 without problem.
         #[expect(clippy::cast_precision_loss)]
-        (DRUM_SAMPLES[usize::from(key)].len() as f64)
+        (voices.kit.sample(key).voice_len() as f64)
     }
-    fn post_event(&mut self) {
-        let vol = f32::from((((7 * i16::try_from(self.base.vol).unwrap()) / 10) - 300) * 8);
-        self.vol_mix_low = 10.0f32.powf(vol / 2000.0);
+    fn post_event(&self, voices: &mut Voices) {
+        voices.vol_mb[1] = (((7 * i32::from(self.base.vol)) / 10) - 300) * 8;
     }
-    fn sample_of_key(&mut self, key: PianoKey, samp_phase: f64) -> StereoSample {
-        let phase_accum = &mut self.base.phases[usize::from(key)];
-        *phase_accum += samp_phase;
+    fn sample_of_key_f64(&self, voices: &mut Voices, key: PianoKey, samp_phase: f64) -> [f64; 2] {
+        let psample = voices.kit.sample(key);
+        let phase_accum = &mut voices.phases[usize::from(key)];
+        *phase_accum += samp_phase * psample.step();
         // Since we use the phase as an index, truncation is expected.
         // We also assume that the phase can never be negative, so sign loss cannot occur.
         debug_assert!(*phase_accum >= 0.0);
         #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
         let ph = *phase_accum as usize;
-        let psample = DRUM_SAMPLES[usize::from(key)];
         if ph >= psample.len() {
-            return [0, 0];
+            return [0.0, 0.0];
         }
         let ph2 = ph + usize::from(ph + 1 != psample.len());
-        let ph_fract = phase_accum.fract();
-        let v0 = f64::from(i16::from(psample[ph]) - 128);
-        let v1 = f64::from(i16::from(psample[ph2]) - 128);
+        let ph_fract = math::fract(*phase_accum);
+        let v0 = f64::from(psample.point(ph));
+        let v1 = f64::from(psample.point(ph2));
         // For percussion keys, every second key has a lower volume
         let vol_mix = if key.is_multiple_of(2) {
-            self.base.vol_mix
+            voices.vol_mix
         } else {
-            self.vol_mix_low
+            voices.vol_mix_low
         };
-        let p = ph_fract.mul_add(v1 - v0, v0) * 256.0 * f64::from(vol_mix);
-        // We assume that the sample can fit within i16 range, and we don't care about
-        // the fractional part.
-        #[expect(clippy::cast_possible_truncation)]
+        let p = math::fma(ph_fract, v1 - v0, v0) * f64::from(vol_mix);
         [
-            (p * f64::from(self.base.vol_left)) as Sample,
-            (p * f64::from(self.base.vol_right)) as Sample,
+            p * f64::from(voices.vol_left),
+            p * f64::from(voices.vol_right),
         ]
     }
+    fn sample_of_key_fixed(
+        &self,
+        voices: &mut Voices,
+        key: PianoKey,
+        samp_phase: u64,
+    ) -> StereoSample {
+        let psample = voices.kit.sample(key);
+        let phase = &mut voices.fixed_phases[usize::from(key)];
+        *phase = phase.saturating_add(psample.fixed_step(samp_phase));
+        let ph = (*phase >> 32) as usize;
+        if ph >= psample.len() {
+            return [0, 0];
+        }
+        let ph2 = ph + usize::from(ph + 1 != psample.len());
+        // The lower 32 bits are the fractional part, of which 24 bits leave room for the gain
+        #[expect(clippy::cast_possible_truncation)]
+        let ph_fract = i64::from((*phase as u32) >> 8);
+        let v0 = i64::from(psample.point(ph));
+        let v1 = i64::from(psample.point(ph2));
+        let p = (v0 << 24) + (v1 - v0) * ph_fract;
+        let gains = voices.fixed_gains[usize::from(!key.is_multiple_of(2))];
+        gains.map(|gain| apply_fixed_gain(p, gain, 24))
+    }
 
-    fn base(&mut self) -> &mut TrackBase {
-        &mut self.base
+    fn base(&self) -> &TrackBase {
+        &self.base
     }
 }
 
@@ -65,7 +86,7 @@ const HAT1: &[u8] = include_bytes!("../../wav/hat1.bin");
 const HAT2: &[u8] = include_bytes!("../../wav/hat2.bin");
 const CYMBAL: &[u8] = include_bytes!("../../wav/cymbal.bin");
 
-/// Percussion samples for each piano key
+/// Built-in percussion samples for each piano key (unsigned 8 bit mono at 22050 Hz)
 pub const DRUM_SAMPLES: [&[u8]; N_KEYS as usize] = [
     BASS1, BASS1, BASS2, BASS2, SNARE, SNARE, SNARE, SNARE, HAT1, HAT1, HAT2, HAT2, CYMBAL, CYMBAL,
     CYMBAL, CYMBAL, CYMBAL, CYMBAL, CYMBAL, CYMBAL, CYMBAL, CYMBAL, CYMBAL, CYMBAL,
@@ -0,0 +1,100 @@
+//! Checks loading drum samples, and playing songs with custom drum kits
+
+use {
+    piyopiyo::{DRUM_SAMPLES, DrumKit, DrumSample, DrumSampleError, Player, Song},
+    std::sync::Arc,
+};
+
+/// A WAVE file with a format chunk of `tag`, `channels`, `rate` and `bits`, and `data`
+fn wav(tag: u16, channels: u16, rate: u32, bits: u16, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(4 + 24 + 8 + data.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&tag.to_le_bytes());
+    out.extend_from_slice(&channels.to_le_bytes());
+    out.extend_from_slice(&rate.to_le_bytes());
+    let block_align = channels * bits / 8;
+    out.extend_from_slice(&(rate * u32::from(block_align)).to_le_bytes());
+    out.extend_from_slice(&block_align.to_le_bytes());
+    out.extend_from_slice(&bits.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+#[test]
+fn load_wav() {
+    let sample = DrumSample::from_wav(&wav(1, 1, 11_025, 8, &[128, 255, 0, 128])).unwrap();
+    assert_eq!((sample.len(), sample.rate()), (4, 11_025));
+    // Two stereo frames, mixed down to mono
+    let data: Vec<u8> = [1000i16, 3000, -200, -400]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+    let sample = DrumSample::from_wav(&wav(1, 2, 44_100, 16, &data)).unwrap();
+    assert_eq!((sample.len(), sample.rate()), (2, 44_100));
+}
+
+#[test]
+fn load_wav_errors() {
+    let valid = wav(1, 1, 22_050, 8, &[128; 16]);
+    let result = DrumSample::from_wav(b"RIFX");
+    assert!(matches!(result, Err(DrumSampleError::InvalidHeader)));
+    let result = DrumSample::from_wav(&wav(3, 1, 22_050, 32, &[0; 16]));
+    assert!(matches!(result, Err(DrumSampleError::UnsupportedFormat)));
+    // Only the RIFF header and the WAVE id
+    let result = DrumSample::from_wav(&valid[..12]);
+    assert!(matches!(result, Err(DrumSampleError::MissingChunk)));
+    let result = DrumSample::from_wav(&valid[..valid.len() - 1]);
+    assert!(matches!(
+        result,
+        Err(DrumSampleError::PrematureEof { offset: 36 })
+    ));
+}
+
+#[test]
+fn default_kit_is_built_in() {
+    let kit = DrumKit::default();
+    for (key, data) in (0..).zip(DRUM_SAMPLES) {
+        assert_eq!(kit.sample(key).len(), data.len());
+        assert_eq!(kit.sample(key).rate(), 22_050);
+    }
+}
+
+/// A song playing every percussion key once
+fn drum_song() -> Song {
+    let mut song = Song::new(24);
+    song.event_wait_ms = 20;
+    for (event, key) in song.percussion_track.base.events.iter_mut().zip(0..) {
+        event.set_key_down(key);
+    }
+    song
+}
+
+fn render(player: &mut Player) -> Vec<i16> {
+    let mut buf = vec![0; 2 * 11_025];
+    player.render_next(&mut buf);
+    buf
+}
+
+#[test]
+fn kit_belongs_to_player() {
+    let silence = DrumSample::from_pcm(Arc::from([0; 256]), 22_050);
+    let silent_kit = DrumKit::from_samples(std::array::from_fn(|_| silence.clone()));
+    let song = Arc::new(drum_song());
+    let mut default_player = Player::from_song(drum_song(), 11_025);
+    let mut player = Player::from_song(drum_song(), 11_025);
+    player.song = Arc::clone(&song);
+    player.set_drum_kit(silent_kit);
+    // The kit doesn't touch the song, so it stays shared
+    assert!(Arc::ptr_eq(&player.song, &song));
+    assert!(render(&mut default_player).iter().any(|&s| s != 0));
+    assert!(render(&mut player).iter().all(|&s| s == 0));
+    // Replacing the song keeps the kit
+    player.song = Arc::new(drum_song());
+    player.seek(0);
+    assert!(render(&mut player).iter().all(|&s| s == 0));
+}
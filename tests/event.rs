@@ -0,0 +1,63 @@
+//! Checks reading and writing the keys and pans of events
+
+use piyopiyo::{Event, N_KEYS, Pan};
+
+#[test]
+fn pan_raw_values() {
+    for (pan, raw) in Pan::ALL.into_iter().zip(1..) {
+        assert_eq!(pan.to_raw(), raw);
+        assert_eq!(u8::from(pan), raw);
+        assert_eq!(Pan::from_raw(raw), Some(pan));
+        assert_eq!(Pan::try_from(raw), Ok(pan));
+    }
+    for raw in [0, 8, 0xFF] {
+        assert_eq!(Pan::from_raw(raw), None);
+        assert_eq!(Pan::try_from(raw), Err(raw));
+    }
+    assert_eq!(Pan::Center.volume_offset(), 0);
+    assert_eq!(Pan::Left3.volume_offset(), 1600);
+    assert_eq!(Pan::Right3.volume_offset(), -1640);
+}
+
+#[test]
+fn set_and_clear_pan() {
+    let mut event = Event::from_bits(0);
+    event.set_key_down(3);
+    event.set_key_down(23);
+    assert_eq!(event.pan_pos(), None);
+    for pan in Pan::ALL {
+        event.set_pan(pan);
+        assert_eq!(event.pan_pos(), Some(pan));
+        assert_eq!(event.raw_pan(), pan.to_raw());
+        assert_eq!(event.pan(), Some(pan.volume_offset()));
+    }
+    event.clear_pan();
+    assert_eq!(event.pan_pos(), None);
+    assert_eq!(event.raw_pan(), 0);
+    // Pans don't touch the keys
+    assert_eq!(event.keys_down().collect::<Vec<_>>(), [3, 23]);
+}
+
+/// Raw pan values outside of the pan table can only come from the bits of an event
+#[test]
+fn invalid_pan() {
+    let mut event = Event::from_bits(0x0800_0001);
+    assert_eq!(event.raw_pan(), 8);
+    assert_eq!(event.pan_pos(), None);
+    assert_eq!(event.pan(), None);
+    event.set_pan(Pan::Right1);
+    assert_eq!(event.bits(), 0x0500_0001);
+}
+
+#[test]
+fn keys_down() {
+    let mut keys = [false; N_KEYS as usize];
+    keys[0] = true;
+    keys[7] = true;
+    keys[23] = true;
+    let mut event = Event::from_keydown_array(keys);
+    assert_eq!(event.keys_down().collect::<Vec<_>>(), [0, 7, 23]);
+    event.set_key_up(7);
+    assert!(!event.key_down(7));
+    assert_eq!(event.bits(), 1 | 1 << 23);
+}
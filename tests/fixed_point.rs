@@ -0,0 +1,184 @@
+//! Checks the accuracy of fixed point mode that `Player::set_fixed_point` documents
+
+use piyopiyo::{
+    Event, Fade, FadeCurve, LoopMode, N_KEYS, Pan, PlaybackEventKind, Player, Song, TrackId,
+    piano_keys,
+};
+
+/// Sample rates at which the documented bound holds
+const RATES: [u32; 4] = [11_025, 22_050, 44_100, 88_200];
+
+/// Xorshift random number generator, so the songs are the same on every run
+struct Rng(u32);
+
+impl Rng {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+    fn below(&mut self, n: u32) -> u32 {
+        self.next() % n
+    }
+    fn byte(&mut self) -> u8 {
+        self.next().to_le_bytes()[0]
+    }
+}
+
+/// A song with random instruments, notes, volumes and pans
+fn random_song(seed: u32) -> Song {
+    let mut rng = Rng(seed);
+    let mut song = Song::new(16);
+    song.event_wait_ms = 30 + rng.below(40);
+    let vols: [u16; 4] = std::array::from_fn(|_| rng.below(301) as u16);
+    for (track, vol) in song.melody_tracks.iter_mut().zip(vols) {
+        track.waveform = std::array::from_fn(|_| rng.byte().cast_signed());
+        track.envelope = std::array::from_fn(|_| rng.byte() / 2);
+        track.octave = rng.below(8) as u8;
+        track.len = rng.below(12_000) as u16;
+        track.base.vol = vol;
+    }
+    song.percussion_track.base.vol = vols[3];
+    let tracks = song
+        .melody_tracks
+        .iter_mut()
+        .map(|track| &mut track.base)
+        .chain([&mut song.percussion_track.base]);
+    for base in tracks {
+        for event in &mut base.events {
+            *event = random_event(&mut rng);
+        }
+    }
+    song
+}
+
+fn random_event(rng: &mut Rng) -> Event {
+    let mut event = Event::from_bits(0);
+    for key in piano_keys() {
+        if rng.below(8) == 0 {
+            event.set_key_down(key);
+        }
+    }
+    if rng.below(4) == 0 {
+        event.set_pan(Pan::ALL[rng.below(7) as usize]);
+    }
+    event
+}
+
+/// Render `song` with a floating point and a fixed point player set up by `setup`, and check
+/// that each frame is within the number of voices playing in it
+///
+/// `setup` is called with the player and the index of the frame about to be rendered.
+fn assert_within_bound(song: &Song, rate: u32, mut setup: impl FnMut(&mut Player, u64)) {
+    let mut float = Player::from_song(song.clone(), rate);
+    let mut fixed = Player::from_song(song.clone(), rate);
+    float.loop_mode = LoopMode::ONCE;
+    fixed.loop_mode = LoopMode::ONCE;
+    fixed.set_fixed_point(true);
+    float.report_events = true;
+    let mut frame = 0;
+    let mut max_diff = 0;
+    loop {
+        for player in [&mut float, &mut fixed] {
+            setup(player, frame);
+        }
+        // Voices play in this frame if they are still playing, or are pressed by the next event
+        let mut playing: Vec<[bool; N_KEYS as usize]> = TrackId::ALL
+            .iter()
+            .flat_map(|&track| [float.voices(track), float.preview_voices(track)])
+            .map(|voices| voices.timers().map(|t| t > 0.0))
+            .collect();
+        let (mut a, mut b) = ([0; 2], [0; 2]);
+        let written = float.render_next(&mut a);
+        assert_eq!(written, fixed.render_next(&mut b));
+        if written == 0 {
+            break;
+        }
+        for event in float.drain_events() {
+            if let PlaybackEventKind::NoteOn { track, key } = event.kind {
+                playing[2 * track.index()][usize::from(key)] = true;
+            }
+        }
+        let n_voices = playing.iter().flatten().filter(|&&p| p).count();
+        for (a, b) in a.into_iter().zip(b) {
+            let diff = usize::from(a.abs_diff(b));
+            assert!(
+                diff <= n_voices,
+                "frame {frame} at {rate} Hz: {a} and {b} differ by more than {n_voices} voices"
+            );
+            max_diff = max_diff.max(diff);
+        }
+        frame += 1;
+    }
+    // The songs are long enough to run into at least one rounding difference
+    assert!(
+        max_diff > 0,
+        "{rate} Hz: no difference at all, check the test"
+    );
+}
+
+#[test]
+fn random_songs() {
+    for seed in 1..=2 {
+        let song = random_song(seed);
+        for rate in RATES {
+            assert_within_bound(&song, rate, |_, _| {});
+        }
+    }
+}
+
+#[test]
+fn transpose_gain_and_fade() {
+    let song = random_song(5);
+    for rate in RATES {
+        assert_within_bound(&song, rate, |player, frame| {
+            if frame == 0 {
+                player.transpose = -7;
+                player.track_controls_mut(TrackId::Melody(1)).transpose = 19;
+                player.track_controls_mut(TrackId::Melody(2)).gain = 0.4;
+                player.track_controls_mut(TrackId::Percussion).gain = 0.75;
+                player.fade = Some(Fade {
+                    duration_ms: 500,
+                    curve: FadeCurve::Cosine,
+                });
+            }
+        });
+    }
+}
+
+#[test]
+fn switching_and_seeking() {
+    let song = random_song(6);
+    for rate in RATES {
+        let frames = u64::from(rate) / 4;
+        assert_within_bound(&song, rate, |player, frame| {
+            // Switching converts the playing voices exactly at these rates
+            if frame == frames {
+                let fixed_point = player.fixed_point();
+                player.set_fixed_point(!fixed_point);
+                player.set_fixed_point(fixed_point);
+            }
+            if frame == 2 * frames {
+                player.seek(frames / 2);
+            }
+        });
+    }
+}
+
+#[test]
+fn previews() {
+    let song = random_song(7);
+    for rate in RATES {
+        assert_within_bound(&song, rate, |player, frame| {
+            if frame == 0 {
+                player.track_controls_mut(TrackId::Melody(0)).mute = true;
+            }
+            if frame % 2000 == 0 {
+                let mut event = Event::from_bits(0);
+                event.set_key_down((frame / 2000 % 24) as u8);
+                player.preview(TrackId::Melody(0), event);
+            }
+        });
+    }
+}
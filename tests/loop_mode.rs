@@ -0,0 +1,94 @@
+//! Checks playing the repeat range a limited number of times, and the end of songs
+
+use piyopiyo::{LoopMode, PlaybackEventKind, Player, Song};
+
+/// Frames per event at 22050 Hz, with the default event wait of 125 ms
+const EVENT_FRAMES: usize = 2757;
+
+fn test_song() -> Song {
+    let mut song = Song::new(8);
+    song.repeat_range = 2..6;
+    song
+}
+
+/// Render up to `frames` frames, returning how many were written and the events that
+/// started, loops and ends of the song that were reported
+fn render(player: &mut Player, frames: usize) -> (usize, Vec<PlaybackEventKind>) {
+    player.report_events = true;
+    let mut buf = vec![0; frames * 2];
+    let written = player.render_next(&mut buf);
+    let events = player
+        .drain_events()
+        .map(|event| event.kind)
+        .filter(|kind| !matches!(kind, PlaybackEventKind::NoteOn { .. }))
+        .collect();
+    (written, events)
+}
+
+fn starts(indices: core::ops::Range<u32>) -> impl Iterator<Item = PlaybackEventKind> {
+    indices.map(|index| PlaybackEventKind::EventStart { index })
+}
+
+#[test]
+fn times_ends_after_n_wraps() {
+    for n in 0..3 {
+        let mut player = Player::from_song(test_song(), 22_050);
+        player.loop_mode = LoopMode::Times(n);
+        let frames = (6 + 4 * n as usize) * EVENT_FRAMES;
+        assert_eq!(player.duration(), Some(frames as u64));
+        let (written, events) = render(&mut player, frames + 10_000);
+        assert_eq!(written, frames);
+        assert!(player.is_finished());
+        let mut expected: Vec<_> = starts(0..6).collect();
+        for loops_done in 1..=n {
+            expected.push(PlaybackEventKind::LoopWrap { loops_done });
+            expected.extend(starts(2..6));
+        }
+        expected.push(PlaybackEventKind::SongEnd);
+        assert_eq!(events, expected);
+        // Nothing is rendered or reported after the end
+        assert_eq!(render(&mut player, 1000), (0, vec![]));
+    }
+}
+
+#[test]
+fn once() {
+    let mut player = Player::from_song(test_song(), 22_050);
+    player.loop_mode = LoopMode::ONCE;
+    let (written, events) = render(&mut player, 100_000);
+    assert_eq!(written, 6 * EVENT_FRAMES);
+    assert_eq!(events.last(), Some(&PlaybackEventKind::SongEnd));
+}
+
+/// The end is only reported once the last event has played completely
+#[test]
+fn end_spans_render_calls() {
+    let mut player = Player::from_song(test_song(), 22_050);
+    player.loop_mode = LoopMode::ONCE;
+    let (written, events) = render(&mut player, 6 * EVENT_FRAMES);
+    assert_eq!(written, 6 * EVENT_FRAMES);
+    assert!(!events.contains(&PlaybackEventKind::SongEnd));
+    assert!(!player.is_finished());
+    assert_eq!(
+        render(&mut player, 10),
+        (0, vec![PlaybackEventKind::SongEnd])
+    );
+    assert!(player.is_finished());
+}
+
+#[test]
+fn forever() {
+    let mut player = Player::from_song(test_song(), 22_050);
+    assert_eq!(player.loop_mode, LoopMode::Forever);
+    assert_eq!(player.duration(), None);
+    let frames = 30 * EVENT_FRAMES;
+    let (written, events) = render(&mut player, frames);
+    assert_eq!(written, frames);
+    assert!(!player.is_finished());
+    assert!(!events.contains(&PlaybackEventKind::SongEnd));
+    let wraps = events
+        .iter()
+        .filter(|kind| matches!(kind, PlaybackEventKind::LoopWrap { .. }))
+        .count();
+    assert_eq!(wraps, 6);
+}
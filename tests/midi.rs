@@ -0,0 +1,150 @@
+//! Checks converting songs to MIDI files and back
+
+use piyopiyo::{Pan, Song, midi};
+
+/// Ticks per event of exported files
+const TICKS_PER_EVENT: u64 = 120;
+
+fn round_trip(song: &Song) -> (Song, midi::ConversionReport) {
+    midi::read(&midi::to_bytes(song).unwrap()).unwrap()
+}
+
+/// A song playing a different key every four events on the first melody track, so no note
+/// cuts off another
+fn test_song(wait_ms: u32, len: u16) -> Song {
+    let mut song = Song::new(32);
+    song.event_wait_ms = wait_ms;
+    song.melody_tracks[0].len = len;
+    let events = song.melody_tracks[0].base.events.iter_mut();
+    for (event, key) in events.step_by(4).zip(0..) {
+        event.set_key_down(key);
+    }
+    song
+}
+
+/// The messages of each track of a MIDI file made by [`midi::to_bytes`], with their ticks
+fn tracks(song: &Song) -> Vec<Vec<(u64, Vec<u8>)>> {
+    let bytes = midi::to_bytes(song).unwrap();
+    let mut tracks = Vec::new();
+    // Skip the header chunk
+    let mut rest = &bytes[14..];
+    while let Some((header, data)) = rest.split_first_chunk::<8>() {
+        assert_eq!(&header[..4], b"MTrk");
+        let len = u32::from_be_bytes(header[4..].try_into().unwrap()) as usize;
+        let (mut chunk, next) = data.split_at(len);
+        rest = next;
+        let mut messages = Vec::new();
+        let mut tick = 0;
+        while !chunk.is_empty() {
+            tick += read_vlq(&mut chunk);
+            let len = match chunk[0] {
+                0xFF => {
+                    let mut data = &chunk[2..];
+                    let len = read_vlq(&mut data);
+                    chunk.len() - data.len() + len as usize
+                }
+                _ => 3,
+            };
+            messages.push((tick, chunk[..len].to_vec()));
+            chunk = &chunk[len..];
+        }
+        tracks.push(messages);
+    }
+    tracks
+}
+
+fn read_vlq(bytes: &mut &[u8]) -> u64 {
+    let mut value = 0;
+    loop {
+        let (&byte, rest) = bytes.split_first().unwrap();
+        *bytes = rest;
+        value = value << 7 | u64::from(byte & 0x7F);
+        if byte & 0x80 == 0 {
+            return value;
+        }
+    }
+}
+
+/// The length of each note of a track, in ticks
+fn note_lengths(messages: &[(u64, Vec<u8>)]) -> Vec<u64> {
+    let mut starts = [0; 128];
+    let mut lengths = Vec::new();
+    for (tick, bytes) in messages {
+        match bytes[0] & 0xF0 {
+            0x90 => starts[usize::from(bytes[1])] = *tick,
+            0x80 => lengths.push(tick - starts[usize::from(bytes[1])]),
+            _ => {}
+        }
+    }
+    lengths
+}
+
+#[test]
+fn note_lengths_are_rounded() {
+    for wait_ms in [40, 100, 125, 333] {
+        for len in (100..12_000).step_by(997) {
+            let tracks = tracks(&test_song(wait_ms, len));
+            let exact =
+                f64::from(len) * TICKS_PER_EVENT as f64 * 1000.0 / (22_050.0 * f64::from(wait_ms));
+            for ticks in note_lengths(&tracks[1]) {
+                assert!(
+                    (ticks as f64 - exact).abs() <= 0.5,
+                    "length {len} at {wait_ms} ms became {ticks} ticks instead of {exact}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn conductor_ends_with_song() {
+    let mut song = test_song(100, 1000);
+    // Empty events after the repeat range
+    song.repeat_range = 4..28;
+    let tracks = tracks(&song);
+    let (end, message) = tracks[0].last().unwrap();
+    assert_eq!(message, &[0xFF, 0x2F, 0x00]);
+    assert_eq!(*end, 32 * TICKS_PER_EVENT);
+}
+
+#[test]
+fn round_trip_keeps_events() {
+    let mut song = Song::new(32);
+    // Empty events after the repeat range
+    song.repeat_range = 4..28;
+    for (track, i) in song.melody_tracks.iter_mut().zip(0..) {
+        track.octave = 2 + i;
+        for (event, j) in track.base.events.iter_mut().zip(0u8..).take(26) {
+            // Both ends of the key range, so the octave can't be mistaken
+            event.set_key_down([0, 23, 7, 12][usize::from(j % 4)]);
+            if j % 5 == i {
+                event.set_pan(Pan::ALL[usize::from(j % 7)]);
+            }
+        }
+    }
+    for (event, j) in song.percussion_track.base.events.iter_mut().zip(0..) {
+        // The even keys of each drum, which is what they're imported as
+        event.set_key_down([0, 2, 4, 8, 10, 12][j % 6]);
+    }
+    let (imported, report) = round_trip(&song);
+    assert!(report.is_lossless(), "{report:?}");
+    assert_eq!(imported.repeat_range, song.repeat_range);
+    let tracks = |song: &Song| -> Vec<Vec<u32>> {
+        song.melody_tracks
+            .iter()
+            .map(|track| &track.base)
+            .chain([&song.percussion_track.base])
+            .map(|base| base.events.iter().map(|event| event.bits()).collect())
+            .collect()
+    };
+    assert_eq!(tracks(&imported), tracks(&song));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn write_matches_to_bytes() {
+    let song = test_song(100, 1000);
+    let mut bytes = Vec::new();
+    midi::write(&song, &mut bytes).unwrap();
+    assert_eq!(bytes, midi::to_bytes(&song).unwrap());
+}
@@ -0,0 +1,86 @@
+//! Checks the floating point mix bus and its clipping
+
+use piyopiyo::{Clipping, Player, Song, sample_from_f32};
+
+/// A song playing chords with the loudest instrument on every melody track, which is far
+/// louder than full scale
+fn loud_song() -> Song {
+    let mut song = Song::new(8);
+    for track in &mut song.melody_tracks {
+        track.waveform = [i8::MIN; 256];
+        track.waveform[128..].fill(i8::MAX);
+        track.envelope = [u8::MAX; 64];
+        track.base.vol = 300;
+        for event in &mut track.base.events {
+            for key in [0, 4, 7, 12] {
+                event.set_key_down(key);
+            }
+        }
+    }
+    song
+}
+
+fn render_f32(song: &Song, clipping: Clipping) -> Vec<f32> {
+    let mut player = Player::from_song(song.clone(), 22_050);
+    player.clipping = clipping;
+    let mut buf = vec![0.0; 20_000];
+    player.render_next_f32(&mut buf);
+    buf
+}
+
+#[test]
+fn clipping() {
+    let song = loud_song();
+    let unclipped = render_f32(&song, Clipping::None);
+    assert!(unclipped.iter().any(|s| s.abs() > 1.5));
+    let hard = render_f32(&song, Clipping::Hard);
+    let soft = render_f32(&song, Clipping::Soft);
+    for ((&s, &hard), &soft) in unclipped.iter().zip(&hard).zip(&soft) {
+        assert_eq!(hard, s.clamp(-1.0, 1.0));
+        assert!(soft.abs() <= 1.0);
+        assert_eq!(soft.signum(), s.signum());
+        if s.abs() <= 0.5 {
+            assert_eq!(soft, s);
+        } else {
+            assert!(soft.abs() > 0.5 && soft.abs() <= s.abs());
+        }
+    }
+}
+
+#[test]
+fn sample_conversion() {
+    assert_eq!(sample_from_f32(0.0), 0);
+    assert_eq!(sample_from_f32(0.5), 16_384);
+    assert_eq!(sample_from_f32(-1.0), i16::MIN);
+    assert_eq!(sample_from_f32(1.0), i16::MAX);
+    assert_eq!(sample_from_f32(-4.0), i16::MIN);
+    assert_eq!(sample_from_f32(4.0), i16::MAX);
+}
+
+/// Songs that don't clip sound the same on both mix buses, apart from quantization
+#[test]
+fn matches_integer_mix() {
+    let mut song = Song::new(8);
+    // Quiet enough that the integer mix doesn't saturate while adding up the tracks
+    song.percussion_track.base.vol = 200;
+    for (track, key) in song.melody_tracks.iter_mut().zip([0, 4, 7]) {
+        track.base.vol = 200;
+        for event in &mut track.base.events {
+            event.set_key_down(key);
+        }
+    }
+    for event in &mut song.percussion_track.base.events {
+        event.set_key_down(4);
+    }
+    let float = render_f32(&song, Clipping::None);
+    let mut player = Player::from_song(song, 22_050);
+    let mut int = vec![0; 20_000];
+    player.render_next(&mut int);
+    assert!(int.iter().any(|&s| s.abs() > 1000));
+    for (&f, &i) in float.iter().zip(&int) {
+        assert!(
+            (i32::from(sample_from_f32(f)) - i32::from(i)).abs() <= 8,
+            "{f} differs from {i}"
+        );
+    }
+}
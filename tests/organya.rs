@@ -0,0 +1,162 @@
+//! Checks converting songs to Organya files
+
+use piyopiyo::{
+    Song, TrackId,
+    organya::{self, ConversionReport, Loss, WaveBank, WriteOptions},
+};
+
+fn export(song: &Song, opts: &WriteOptions) -> (Vec<u8>, ConversionReport) {
+    organya::to_bytes(song, opts).unwrap()
+}
+
+/// The instruments of the Organya tracks, from the losses of importing `bytes`
+fn instruments(bytes: &[u8]) -> Vec<(u8, u8)> {
+    let (_, report) = organya::read(bytes).unwrap();
+    report
+        .losses
+        .into_iter()
+        .filter_map(|loss| match loss {
+            Loss::InstrumentReplaced { track, instrument } => Some((track, instrument)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A song playing one note at a time on each melody track
+fn test_song() -> Song {
+    let mut song = Song::new(16);
+    for (track, i) in song.melody_tracks.iter_mut().zip(0..) {
+        for (event, j) in track.base.events.iter_mut().zip(0..).step_by(2) {
+            event.set_key_down((i * 3 + j) % 24);
+        }
+    }
+    song
+}
+
+/// A wave bank of sawtooth waves at different frequencies, with `waves` at the start
+fn wave_bank(waves: &[[i8; 256]]) -> Box<WaveBank> {
+    let mut data = vec![0; 100 * 256];
+    for (wave, freq) in data.chunks_exact_mut(256).zip(1..) {
+        for (sample, i) in wave.iter_mut().zip(0u32..) {
+            *sample = (i * freq % 256) as u8;
+        }
+    }
+    for (wave, chunk) in waves.iter().zip(data.chunks_exact_mut(256)) {
+        for (&sample, byte) in wave.iter().zip(chunk) {
+            *byte = sample.cast_unsigned();
+        }
+    }
+    organya::wave_bank_from_bytes(&data).unwrap()
+}
+
+#[test]
+fn wave_bank_from_bytes() {
+    assert!(organya::wave_bank_from_bytes(&[0; 100 * 256 - 1]).is_none());
+    let bank = organya::wave_bank_from_bytes(&[0xFF; 100 * 256 + 1]).unwrap();
+    assert!(bank.iter().flatten().all(|&s| s == -1));
+}
+
+#[test]
+fn instruments_default_to_0() {
+    let (bytes, report) = export(&test_song(), &WriteOptions::default());
+    assert_eq!(instruments(&bytes), [(0, 0), (1, 0), (2, 0)]);
+    assert_eq!(
+        report.losses,
+        (0..3)
+            .map(|i| Loss::InstrumentDefaulted {
+                track: TrackId::Melody(i)
+            })
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn instruments_from_wave_bank() {
+    let mut song = test_song();
+    let mut square = [0; 256];
+    square[..128].fill(100);
+    square[128..].fill(-100);
+    song.melody_tracks[1].waveform = square;
+    // Track 0 is in the bank, track 1 is similar to its bank entry, and track 2 is chosen
+    let mut in_bank = square;
+    in_bank[0] = 90;
+    let bank = wave_bank(&[[0; 256], [0; 256], song.melody_tracks[0].waveform, in_bank]);
+    let opts = WriteOptions {
+        instruments: [None, None, Some(42)],
+        wave_bank: Some(&bank),
+    };
+    let (bytes, report) = export(&song, &opts);
+    assert_eq!(instruments(&bytes), [(0, 2), (1, 3), (2, 42)]);
+    assert_eq!(
+        report.losses,
+        [Loss::InstrumentApproximated {
+            track: TrackId::Melody(1),
+            instrument: 3
+        }]
+    );
+}
+
+#[test]
+fn chords_are_reported() {
+    let mut song = test_song();
+    // 4 + 3 + 3 notes in a chord, one more than there are Organya tracks
+    for (track, n_keys) in song.melody_tracks.iter_mut().zip([4, 3, 3]) {
+        for key in 0..n_keys {
+            track.base.events[1].set_key_down(key * 4);
+        }
+    }
+    let opts = WriteOptions {
+        instruments: [Some(1); 3],
+        wave_bank: None,
+    };
+    let (bytes, report) = export(&song, &opts);
+    let melody = TrackId::Melody;
+    assert_eq!(
+        report.losses,
+        [
+            Loss::ChordsSplit {
+                track: melody(0),
+                tracks: 3
+            },
+            Loss::NotesDropped {
+                track: melody(0),
+                notes: 1
+            },
+            Loss::ChordsSplit {
+                track: melody(1),
+                tracks: 3
+            },
+            Loss::ChordsSplit {
+                track: melody(2),
+                tracks: 2
+            },
+            Loss::NotesDropped {
+                track: melody(2),
+                notes: 1
+            },
+        ]
+    );
+    assert_eq!(instruments(&bytes).len(), 8);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn write_matches_to_bytes() {
+    let opts = WriteOptions::default();
+    let mut bytes = Vec::new();
+    let report = organya::write(&test_song(), &opts, &mut bytes).unwrap();
+    assert_eq!((bytes, report), export(&test_song(), &opts));
+}
+
+#[test]
+fn too_many_notes() {
+    let mut song = Song::new(70_000);
+    for event in &mut song.melody_tracks[0].base.events {
+        event.set_key_down(0);
+    }
+    let err = organya::to_bytes(&song, &WriteOptions::default()).unwrap_err();
+    assert!(matches!(
+        err,
+        organya::WriteError::TooManyNotes { track: 0 }
+    ));
+}
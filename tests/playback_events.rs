@@ -0,0 +1,109 @@
+//! Checks the playback events reported by `Player::drain_events`
+
+use piyopiyo::{Pan, PlaybackEvent, PlaybackEventKind, Player, Song, TrackId};
+
+/// Frames per event at 22050 Hz, with the default event wait of 125 ms
+const EVENT_FRAMES: usize = 2757;
+
+/// A song with a note on the first melody track and the percussion track every other event,
+/// and a pan change on event 3
+fn test_song() -> Song {
+    let mut song = Song::new(8);
+    for event in song.melody_tracks[0].base.events.iter_mut().step_by(2) {
+        event.set_key_down(5);
+    }
+    for event in song.percussion_track.base.events.iter_mut().step_by(2) {
+        event.set_key_down(1);
+    }
+    song.melody_tracks[2].base.events[3].set_pan(Pan::Left2);
+    song
+}
+
+/// Render `frames` frames in buffers of `chunk` frames, draining the events after each call,
+/// with their offsets made relative to the start of playback
+fn render_events(player: &mut Player, frames: usize, chunk: usize) -> Vec<(usize, PlaybackEvent)> {
+    let mut events = Vec::new();
+    let mut buf = vec![0.0; chunk * 2];
+    for start in (0..frames).step_by(chunk) {
+        let len = chunk.min(frames - start);
+        player.render_next_f32(&mut buf[..len * 2]);
+        for event in player.drain_events() {
+            assert!(event.offset < chunk);
+            events.push((start + event.offset, event));
+        }
+    }
+    events
+}
+
+#[test]
+fn events_have_exact_offsets() {
+    let mut player = Player::from_song(test_song(), 22_050);
+    player.report_events = true;
+    let events = render_events(&mut player, 8 * EVENT_FRAMES, 1000);
+    let melody = TrackId::Melody;
+    let mut expected = Vec::new();
+    for index in 0..8 {
+        let frame = index as usize * EVENT_FRAMES;
+        expected.push((frame, PlaybackEventKind::EventStart { index }));
+        if index % 2 == 0 {
+            let note_on = |track, key| PlaybackEventKind::NoteOn { track, key };
+            expected.push((frame, note_on(melody(0), 5)));
+            expected.push((frame, note_on(TrackId::Percussion, 1)));
+        }
+        if index == 3 {
+            let pan = Pan::Left2;
+            expected.push((
+                frame,
+                PlaybackEventKind::PanChange {
+                    track: melody(2),
+                    pan,
+                },
+            ));
+        }
+    }
+    let actual: Vec<_> = events
+        .iter()
+        .map(|&(frame, event)| (frame, event.kind))
+        .collect();
+    assert_eq!(actual, expected);
+    // Before the song loops, positions count frames from the start of playback
+    for (frame, event) in events {
+        assert_eq!(event.position, frame as u64);
+    }
+}
+
+/// The size of the buffers only changes the offsets, not when events happen
+#[test]
+fn buffer_size_doesnt_matter() {
+    let events = |chunk| {
+        let mut player = Player::from_song(test_song(), 22_050);
+        player.report_events = true;
+        render_events(&mut player, 20 * EVENT_FRAMES, chunk)
+            .into_iter()
+            .map(|(frame, event)| (frame, event.position, event.kind))
+            .collect::<Vec<_>>()
+    };
+    let expected = events(20 * EVENT_FRAMES);
+    assert!(
+        expected
+            .iter()
+            .any(|(_, _, kind)| matches!(kind, PlaybackEventKind::LoopWrap { .. }))
+    );
+    for chunk in [1, 441, EVENT_FRAMES, 4096] {
+        assert_eq!(events(chunk), expected, "buffers of {chunk} frames");
+    }
+}
+
+#[test]
+fn off_by_default() {
+    let mut player = Player::from_song(test_song(), 22_050);
+    assert!(!player.report_events);
+    render_events(&mut player, 4 * EVENT_FRAMES, 1000);
+    assert_eq!(player.drain_events().count(), 0);
+    // Events are drained, so they are only taken once
+    player.report_events = true;
+    let mut buf = vec![0; 100];
+    player.render_next(&mut buf);
+    assert!(player.drain_events().count() > 0);
+    assert_eq!(player.drain_events().count(), 0);
+}
@@ -0,0 +1,250 @@
+//! Checks playback with `Player`, including songs that `Song::validate` has problems with
+
+use piyopiyo::{
+    Event, Fade, FadeCurve, Interpolation, LoopMode, PlaybackEventKind, Player, Song, Timing,
+    TrackId,
+};
+
+/// Render `frames` stereo frames of `player`, with playback events enabled
+fn render(player: &mut Player, frames: usize) -> (Vec<i16>, Vec<PlaybackEventKind>) {
+    player.report_events = true;
+    let mut buf = vec![0; frames * 2];
+    player.render_next(&mut buf);
+    let events = player.drain_events().map(|event| event.kind).collect();
+    (buf, events)
+}
+
+#[test]
+fn empty_song() {
+    let mut player = Player::from_song(Song::new(0), 22_050);
+    assert_eq!(player.n_events(), 0);
+    let (buf, _) = render(&mut player, 10_000);
+    assert!(buf.iter().all(|&s| s == 0));
+    player.seek(5000);
+    render(&mut player, 1000);
+}
+
+#[test]
+fn mismatched_track_lengths() {
+    let mut song = Song::new(8);
+    for track in &mut song.melody_tracks {
+        for event in &mut track.base.events {
+            event.set_key_down(0);
+        }
+    }
+    let events = song.melody_tracks[1].base.events[..3].to_vec();
+    song.melody_tracks[1].base.events = events.into_boxed_slice();
+    song.percussion_track.base.events = Box::new([]);
+    let mut player = Player::from_song(song, 22_050);
+    assert_eq!(player.n_events(), 8);
+    // Two passes through the song
+    let (_, events) = render(&mut player, 22_050 * 2);
+    let mut index = 0;
+    let mut notes = [0; 4];
+    for event in events {
+        match event {
+            PlaybackEventKind::EventStart { index: i } => index = i,
+            PlaybackEventKind::NoteOn { track, .. } => {
+                assert!(
+                    track != TrackId::Melody(1) || index < 3,
+                    "{track} played {index}"
+                );
+                notes[track.index()] += 1;
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(notes, [16, 6, 16, 0]);
+    player.seek(10_000);
+    render(&mut player, 1000);
+}
+
+/// Octaves far outside of the valid range play without overflowing, in every mode
+#[test]
+fn out_of_range_octave() {
+    let mut song = Song::new(4);
+    for track in &mut song.melody_tracks {
+        track.octave = 255;
+        for event in &mut track.base.events {
+            event.set_key_down(23);
+        }
+    }
+    let interpolations = [
+        Interpolation::Nearest,
+        Interpolation::Linear,
+        Interpolation::Cubic,
+        Interpolation::BandLimited,
+    ];
+    for interpolation in interpolations {
+        let mut player = Player::from_song(song.clone(), 44_100);
+        player.set_interpolation(interpolation);
+        player.transpose = 127;
+        render(&mut player, 10_000);
+        let mut buf = vec![0.0; 20_000];
+        player.render_next_f32(&mut buf);
+        assert!(buf.iter().all(|s| s.is_finite()), "{interpolation:?}");
+    }
+}
+
+/// The offset of each event start in the first `frames` frames of `player`
+fn event_starts(player: &mut Player, frames: usize) -> Vec<usize> {
+    player.report_events = true;
+    let mut buf = vec![0; frames * 2];
+    player.render_next(&mut buf);
+    player
+        .drain_events()
+        .filter(|event| matches!(event.kind, PlaybackEventKind::EventStart { .. }))
+        .map(|event| event.offset)
+        .collect()
+}
+
+#[test]
+fn speed() {
+    let mut player = Player::from_song(Song::new(64), 22_050);
+    player.timing = Timing::Exact;
+    player.speed = 2.0;
+    // 125 ms per event at 22050 Hz, played at twice the speed
+    let expected: Vec<_> = (0..40).map(|n| n * 22_050 * 125 / 2000).collect();
+    assert_eq!(event_starts(&mut player, 55_125), expected);
+    // Speeds out of range play at the fastest (one frame per event) or slowest speed
+    for speed in [f64::INFINITY, f64::MAX, 1e30] {
+        let mut player = Player::from_song(Song::new(64), 22_050);
+        player.speed = speed;
+        assert_eq!(event_starts(&mut player, 64), (0..64).collect::<Vec<_>>());
+        player.seek(1000);
+        assert_eq!(player.position(), 1000);
+    }
+    for speed in [0.0, -1.0, f64::NEG_INFINITY, f64::NAN] {
+        let mut player = Player::from_song(Song::new(64), 22_050);
+        player.speed = speed;
+        assert_eq!(event_starts(&mut player, 100_000), [0]);
+    }
+}
+
+#[test]
+fn timing() {
+    // 125 ms per event is 5512.5 frames at 44100 Hz
+    let mut player = Player::from_song(Song::new(64), 44_100);
+    assert_eq!(player.timing, Timing::Truncated);
+    let expected: Vec<_> = (0..20).map(|n| n * 5513).collect();
+    assert_eq!(event_starts(&mut player, 20 * 5513 - 1), expected);
+    let mut player = Player::from_song(Song::new(64), 44_100);
+    player.timing = Timing::Exact;
+    let expected: Vec<_> = (0..20).map(|n| n * 11_025 / 2).collect();
+    assert_eq!(event_starts(&mut player, 110_250), expected);
+}
+
+#[test]
+fn transpose() {
+    let song = |octave| {
+        let mut song = Song::new(8);
+        for (event, key) in song.melody_tracks[0].base.events.iter_mut().zip(0..) {
+            event.set_key_down(key * 3);
+        }
+        song.melody_tracks[0].octave = octave;
+        for event in &mut song.percussion_track.base.events {
+            event.set_key_down(0);
+        }
+        song
+    };
+    let render = |player: &mut Player| {
+        let mut buf = vec![0; 22_050];
+        player.render_next(&mut buf);
+        buf
+    };
+    let expected = render(&mut Player::from_song(song(4), 22_050));
+    // An octave up shifts the melody tracks, but not the percussion track
+    let mut player = Player::from_song(song(3), 22_050);
+    player.transpose = 12;
+    assert!(render(&mut player) == expected);
+    // Track transposes add to the player transpose
+    let mut player = Player::from_song(song(5), 22_050);
+    player.transpose = -24;
+    player.track_controls_mut(TrackId::Melody(0)).transpose = 12;
+    player.track_controls_mut(TrackId::Percussion).transpose = 5;
+    assert!(render(&mut player) == expected);
+}
+
+/// Seeking only restores the voices of the song, so previews play on undisturbed
+#[test]
+fn seek_keeps_previews() {
+    let mut event = Event::from_bits(0);
+    event.set_key_down(5);
+    let render = |player: &mut Player, frames: usize| {
+        let mut buf = vec![0; frames * 2];
+        player.render_next(&mut buf);
+        buf
+    };
+    let mut player = Player::from_song(Song::new(64), 22_050);
+    player.preview(TrackId::Melody(0), event);
+    let expected = render(&mut player, 4000);
+    let mut player = Player::from_song(Song::new(64), 22_050);
+    player.preview(TrackId::Melody(0), event);
+    let mut buf = render(&mut player, 2000);
+    player.seek(100_000);
+    buf.extend(render(&mut player, 2000));
+    assert!(buf == expected);
+}
+
+/// Render all of a song playing a note on every event with `fade`, and the gain of the fade at
+/// each frame where the song is audible
+fn fade_gains(repeat_range: core::ops::Range<u32>, fade: Option<Fade>) -> Vec<(u64, f32)> {
+    let mut song = Song::new(16);
+    song.repeat_range = repeat_range;
+    for event in &mut song.melody_tracks[0].base.events {
+        event.set_key_down(0);
+    }
+    let render = |fade| {
+        let mut player = Player::from_song(song.clone(), 44_100);
+        player.loop_mode = LoopMode::ONCE;
+        player.fade = fade;
+        let mut buf = vec![0.0; 16 * 5513 * 2];
+        player.render_next_f32(&mut buf);
+        buf
+    };
+    let full = render(None);
+    let faded = render(fade);
+    (0..)
+        .zip(full.chunks_exact(2).zip(faded.chunks_exact(2)))
+        .filter(|(_, (full, _))| full[0].abs() > 0.01)
+        .map(|(pos, (full, faded))| (pos, faded[0] / full[0]))
+        .collect()
+}
+
+/// The gain of `curve` at `t` into the fade
+fn curve_gain(curve: FadeCurve, t: f64) -> f64 {
+    match curve {
+        FadeCurve::Linear => 1.0 - t,
+        FadeCurve::Exponential => 10f64.powf(-3.0 * t),
+        FadeCurve::Cosine => 0.5 * (1.0 + (std::f64::consts::PI * t).cos()),
+    }
+}
+
+#[test]
+fn fade_gain() {
+    let end = 16 * 5513;
+    let fade = |duration_ms, curve| Some(Fade { duration_ms, curve });
+    let curves = [FadeCurve::Linear, FadeCurve::Exponential, FadeCurve::Cosine];
+    for curve in curves {
+        // A one second fade, and a fade that is shortened to the repeat range
+        for (range, duration_ms, fade_start) in
+            [(0..16, 1000, end - 44_100), (8..16, 5000, end / 2)]
+        {
+            for (pos, actual) in fade_gains(range, fade(duration_ms, curve)) {
+                let expected = if pos < fade_start {
+                    1.0
+                } else {
+                    curve_gain(curve, (pos - fade_start) as f64 / (end - fade_start) as f64)
+                };
+                assert!(
+                    (f64::from(actual) - expected).abs() < 1e-3,
+                    "{curve:?} fade at {pos} is {actual} instead of {expected}"
+                );
+            }
+        }
+    }
+    // Songs that loop forever never end, so they aren't faded
+    let mut player = Player::from_song(Song::new(16), 44_100);
+    player.fade = fade(1000, FadeCurve::Linear);
+    assert_eq!(player.duration(), None);
+}
@@ -0,0 +1,173 @@
+//! Checks loading and saving of PMD files
+
+use piyopiyo::{Event, LoadError, Location, Pan, Player, Problem, Song};
+
+/// A song with a few notes and pans on every track
+fn test_song() -> Song {
+    let mut song = Song::new(12);
+    song.header_extra = [1, 2, 3, 4, 5];
+    song.event_wait_ms = 90;
+    song.repeat_range = 2..10;
+    song.melody_tracks[1].octave = 5;
+    song.melody_tracks[2].len = 1234;
+    song.percussion_track.base.vol = 120;
+    let tracks = song
+        .melody_tracks
+        .iter_mut()
+        .map(|track| &mut track.base)
+        .chain([&mut song.percussion_track.base]);
+    for (base, i) in tracks.zip(0u8..) {
+        for (event, j) in base.events.iter_mut().zip(0u8..) {
+            event.set_key_down((i * 5 + j) % 24);
+            if j % 3 == 0 {
+                event.set_pan(Pan::ALL[usize::from(j) % 7]);
+            }
+        }
+    }
+    song
+}
+
+fn event_bits(events: &[Event]) -> Vec<u32> {
+    events.iter().map(|event| event.bits()).collect()
+}
+
+#[test]
+fn round_trip() {
+    let bytes = test_song().to_bytes();
+    let song = Song::load(&bytes).unwrap();
+    assert_eq!(song.to_bytes(), bytes);
+}
+
+#[test]
+fn round_trip_trailing_bytes() {
+    let mut bytes = test_song().to_bytes();
+    bytes.extend_from_slice(b"trailing garbage");
+    let song = Song::load(&bytes).unwrap();
+    assert_eq!(song.trailing_bytes, b"trailing garbage");
+    assert_eq!(song.to_bytes(), bytes);
+}
+
+#[test]
+fn shorter_tracks_are_padded() {
+    let mut song = test_song();
+    let events = song.melody_tracks[1].base.events[..5].to_vec();
+    song.melody_tracks[1].base.events = events.into_boxed_slice();
+    let loaded = Song::load(&song.to_bytes()).unwrap();
+    let padded = &loaded.melody_tracks[1].base.events;
+    assert_eq!(padded.len(), 12);
+    assert_eq!(
+        event_bits(&padded[..5]),
+        event_bits(&song.melody_tracks[1].base.events)
+    );
+    assert!(padded[5..].iter().all(|event| event.bits() == 0));
+    assert_eq!(
+        event_bits(&loaded.percussion_track.base.events),
+        event_bits(&song.percussion_track.base.events)
+    );
+}
+
+/// Byte offset of the repeat start, repeat end and event count fields
+const REPEAT_START: usize = 12;
+const REPEAT_END: usize = 16;
+const EVENT_COUNT: usize = 20;
+
+fn with_u32(mut bytes: Vec<u8>, offset: usize, value: u32) -> Vec<u8> {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    bytes
+}
+
+#[test]
+fn truncated() {
+    let bytes = test_song().to_bytes();
+    for len in 0..bytes.len() {
+        let result = Song::load(&bytes[..len]);
+        match result {
+            Err(LoadError::InvalidMagic) => assert!(len < 3),
+            Err(LoadError::PrematureEof { offset, .. }) => assert!(offset <= len),
+            Err(e) => panic!("truncated to {len} bytes: unexpected error {e}"),
+            Ok(_) => panic!("truncated to {len} bytes: loaded successfully"),
+        }
+    }
+}
+
+/// Songs without events load, and are left to `Song::validate`
+#[test]
+fn no_events() {
+    let mut bytes = test_song().to_bytes();
+    // Only the header is left
+    bytes.truncate(bytes.len() - 4 * 12 * 4);
+    let bytes = with_u32(bytes, EVENT_COUNT, 0);
+    let song = Song::load(&bytes).unwrap();
+    assert!(
+        song.validate()
+            .iter()
+            .any(|diag| diag.problem == Problem::NoEvents)
+    );
+    Player::from_song(song, 11_025).render_next(&mut [0; 2048]);
+}
+
+/// Songs with a repeat range past the last event load, and are left to `Song::validate`
+#[test]
+fn repeat_range_past_end() {
+    let bytes = test_song().to_bytes();
+    for (start, end) in [(0, 13), (12, 12), (12, 13), (13, 5), (2, u32::MAX)] {
+        let bytes = with_u32(
+            with_u32(bytes.clone(), REPEAT_START, start),
+            REPEAT_END,
+            end,
+        );
+        let song = Song::load(&bytes).unwrap();
+        assert_eq!(song.repeat_range, start..end);
+        let past_end = Problem::RepeatRangePastEnd { n_events: 12 };
+        assert!(
+            song.validate()
+                .iter()
+                .any(|diag| (diag.location, diag.problem) == (Location::Song, past_end)),
+            "{start}..{end} isn't reported"
+        );
+        let mut player = Player::from_song(song, 11_025);
+        for _ in 0..4 {
+            player.render_next(&mut [0; 2048]);
+        }
+    }
+}
+
+/// Songs that load successfully must play without panicking, whatever the bytes
+#[test]
+fn garbage() {
+    let template = test_song().to_bytes();
+    let mut state = 0x1234_5678_u32;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+    let mut n_loaded = 0;
+    for _ in 0..300 {
+        let mut bytes = template.clone();
+        for _ in 0..1 + next() % 4 {
+            // Hit the repeat range and event count often, since the player indexes with them.
+            // Small values keep these fields plausible.
+            let (offset, value) = if next() % 2 == 0 {
+                (REPEAT_START + next() as usize % 12, next() % 16)
+            } else {
+                (next() as usize % bytes.len(), next())
+            };
+            bytes[offset] = value.to_le_bytes()[0];
+        }
+        let Ok(song) = Song::load(&bytes) else {
+            continue;
+        };
+        n_loaded += 1;
+        let mut player = Player::from_song(song, 11_025);
+        let mut buf = [0; 2048];
+        for _ in 0..4 {
+            player.render_next(&mut buf);
+        }
+    }
+    assert!(
+        n_loaded > 100,
+        "only {n_loaded} songs loaded, check the test"
+    );
+}
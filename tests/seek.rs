@@ -0,0 +1,60 @@
+//! Checks that seeking gives the same samples as playing up to the seek position
+
+use piyopiyo::{Event, N_KEYS, Pan, Player, Song, Timing};
+
+const RATES: [u32; 3] = [22_050, 44_100, 48_000];
+
+/// A song with notes ringing over several events, and pans right before the repeat start
+/// and at the end of the repeat range
+fn test_song() -> Song {
+    let mut song = Song::new(12);
+    song.event_wait_ms = 40;
+    song.repeat_range = 3..9;
+    let tracks = song
+        .melody_tracks
+        .iter_mut()
+        .map(|track| &mut track.base)
+        .chain([&mut song.percussion_track.base]);
+    for (base, i) in tracks.zip(0..) {
+        for (event, j) in base.events.iter_mut().zip(0..) {
+            if (i + j) % 3 != 0 {
+                event.set_key_down((i * 7 + j * 5) % N_KEYS);
+            }
+        }
+        base.events[2].set_pan(Pan::ALL[usize::from(i)]);
+        base.events[8].set_pan(Pan::ALL[6 - usize::from(i)]);
+    }
+    song.melody_tracks[1].base.events[5] = Event::from_bits(0);
+    song
+}
+
+fn player(song: &Song, rate: u32, timing: Timing) -> Player {
+    let mut player = Player::new(&song.to_bytes(), rate).unwrap();
+    player.timing = timing;
+    player
+}
+
+#[test]
+fn seek_matches_linear_playback() {
+    let song = test_song();
+    for rate in RATES {
+        for timing in [Timing::Exact, Timing::Truncated] {
+            // Three passes through the repeat range
+            let frames = rate as usize * 40 * 24 / 1000;
+            let mut linear = vec![0; frames * 2];
+            player(&song, rate, timing).render_next(&mut linear);
+            let check_frames = 256;
+            for k in (0..frames - check_frames).step_by(frames / 19) {
+                let mut seeked = player(&song, rate, timing);
+                seeked.seek(k as u64);
+                assert_eq!(seeked.position(), k as u64);
+                let mut buf = vec![0; check_frames * 2];
+                seeked.render_next(&mut buf);
+                assert!(
+                    buf == linear[k * 2..(k + check_frames) * 2],
+                    "seeking to {k} at {rate} Hz with {timing:?} timing differs"
+                );
+            }
+        }
+    }
+}
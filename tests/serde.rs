@@ -0,0 +1,65 @@
+//! Checks serializing and deserializing songs
+#![cfg(feature = "serde")]
+
+use piyopiyo::{Event, Location, Pan, Player, Problem, Song, TrackId};
+
+/// A song with notes and pans on every track, and bytes that are only preserved for saving
+fn test_song() -> Song {
+    let mut song = Song::new(12);
+    song.header_extra = [1, 2, 3, 4, 5];
+    song.trailing_bytes = b"trailing".to_vec();
+    song.repeat_range = 2..10;
+    song.melody_tracks[1].octave = 5;
+    song.melody_tracks[2].waveform[7] = -128;
+    song.percussion_track.base.vol = 120;
+    for (event, i) in song.melody_tracks[0].base.events.iter_mut().zip(0u8..) {
+        event.set_key_down(i % 24);
+        event.set_pan(Pan::ALL[usize::from(i) % 7]);
+    }
+    song
+}
+
+fn round_trip(song: &Song) -> Song {
+    ron::from_str(&ron::to_string(song).unwrap()).unwrap()
+}
+
+#[test]
+fn round_trip_keeps_song() {
+    let song = test_song();
+    assert_eq!(round_trip(&song).to_bytes(), song.to_bytes());
+}
+
+#[test]
+fn preserved_bytes_default_to_empty() {
+    let text = ron::to_string(&test_song()).unwrap();
+    let text = text
+        .replace("header_extra:(1,2,3,4,5),", "")
+        .replace(",trailing_bytes:[116,114,97,105,108,105,110,103]", "");
+    let song: Song = ron::from_str(&text).unwrap();
+    assert_eq!(song.header_extra, [0; 5]);
+    assert!(song.trailing_bytes.is_empty());
+}
+
+/// Deserialized songs skip the checks of `Song::load`, and still have to play
+#[test]
+fn invalid_song_plays() {
+    let mut song = test_song();
+    song.melody_tracks[0].base.events[3] = Event::from_bits(0xFF00_0001);
+    song.melody_tracks[2].base.events = Box::new([]);
+    let song = round_trip(&song);
+    let problems: Vec<_> = song
+        .validate()
+        .into_iter()
+        .map(|diag| (diag.location, diag.problem))
+        .collect();
+    let invalid_pan = (
+        Location::Event(TrackId::Melody(0), 3),
+        Problem::InvalidPan(0xFF),
+    );
+    assert!(problems.contains(&invalid_pan));
+    let melody_2 = Location::Track(TrackId::Melody(2));
+    assert!(problems.iter().any(|&(location, _)| location == melody_2));
+    let mut player = Player::from_song(song, 22_050);
+    let mut buf = vec![0; 22_050 * 4];
+    assert_eq!(player.render_next(&mut buf), 22_050 * 2);
+}
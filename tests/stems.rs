@@ -0,0 +1,98 @@
+//! Checks rendering each track separately
+
+use piyopiyo::{Pan, Player, Song, TrackId};
+
+/// A song with chords and pan changes on every track, quiet enough that mixing it doesn't
+/// saturate
+fn test_song() -> Song {
+    let mut song = Song::new(16);
+    song.repeat_range = 4..12;
+    let tracks = song
+        .melody_tracks
+        .iter_mut()
+        .map(|track| &mut track.base)
+        .chain([&mut song.percussion_track.base]);
+    for (base, i) in tracks.zip(0..) {
+        base.vol = 50;
+        for (event, j) in base.events.iter_mut().zip(0..) {
+            event.set_key_down((i * 5 + j * 3) % 24);
+            event.set_key_down((i * 5 + j * 7 + 4) % 24);
+            if j % 3 == 0 {
+                event.set_pan(Pan::ALL[usize::from(i + j) % 7]);
+            }
+        }
+    }
+    song
+}
+
+fn player() -> Player {
+    let mut player = Player::from_song(test_song(), 44_100);
+    player.track_controls_mut(TrackId::Melody(1)).mute = true;
+    player
+}
+
+const FRAMES: usize = 30_000;
+
+#[test]
+fn stems_sum_to_mix() {
+    let mut mix = vec![0; FRAMES * 2];
+    player().render_next(&mut mix);
+    let mut stems = vec![[[0; 2]; 4]; FRAMES];
+    player().render_stems(&mut stems);
+    assert!(stems.iter().all(|stems| stems[1] == [0; 2]));
+    let summed: Vec<_> = stems
+        .iter()
+        .flat_map(|stems| {
+            stems.iter().fold([0i16; 2], |[l, r], [sl, sr]| {
+                [l.saturating_add(*sl), r.saturating_add(*sr)]
+            })
+        })
+        .collect();
+    assert!(mix.iter().any(|&s| s.abs() > 1000));
+    assert!(summed == mix);
+}
+
+#[test]
+fn f32_stems_sum_to_mix() {
+    let mut mix = vec![0.0; FRAMES * 2];
+    player().render_next_f32(&mut mix);
+    let mut stems = vec![[[0.0; 2]; 4]; FRAMES];
+    player().render_stems_f32(&mut stems);
+    for (frame, stems) in mix.chunks_exact(2).zip(&stems) {
+        for (channel, &mixed) in frame.iter().enumerate() {
+            let summed: f32 = stems.iter().map(|stem| stem[channel]).sum();
+            assert!(
+                (summed - mixed).abs() < 1e-5,
+                "{summed} differs from {mixed}"
+            );
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn wav_stems() {
+    use piyopiyo::export::wav::{self, WavOptions};
+
+    let song = test_song();
+    let opts = WavOptions {
+        loops: 2,
+        ..WavOptions::default()
+    };
+    let mut files: [Vec<u8>; 4] = Default::default();
+    wav::write_stems(&song, &opts, files.each_mut()).unwrap();
+    let mut player = Player::from_song(song, 44_100);
+    player.loop_mode = piyopiyo::LoopMode::Times(1);
+    let frames = usize::try_from(player.duration().unwrap()).unwrap();
+    let mut stems = vec![[[0; 2]; 4]; frames];
+    assert_eq!(player.render_stems(&mut stems), frames);
+    for (file, idx) in files.iter().zip(0..) {
+        // The header of 16 bit files is 44 bytes long
+        let expected: Vec<u8> = stems
+            .iter()
+            .flat_map(|stems| stems[idx])
+            .flat_map(i16::to_le_bytes)
+            .collect();
+        assert!(file[44..] == expected, "stem {idx} differs");
+    }
+}
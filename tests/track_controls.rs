@@ -0,0 +1,79 @@
+//! Checks muting, soloing and the gain of tracks
+
+use piyopiyo::{Player, Song, TrackId};
+
+/// A song with a different key on every track, or only on the tracks in `playing`
+fn test_song(playing: &[TrackId]) -> Song {
+    let mut song = Song::new(8);
+    let tracks = song
+        .melody_tracks
+        .iter_mut()
+        .map(|track| &mut track.base)
+        .chain([&mut song.percussion_track.base]);
+    for ((base, id), key) in tracks.zip(TrackId::ALL).zip([0, 4, 7, 2]) {
+        if playing.contains(&id) {
+            for event in &mut base.events {
+                event.set_key_down(key);
+            }
+        }
+    }
+    song
+}
+
+fn render(player: &mut Player) -> Vec<f32> {
+    let mut buf = vec![0.0; 20_000];
+    player.render_next_f32(&mut buf);
+    buf
+}
+
+/// The output of a player that only plays `playing`
+fn only(playing: &[TrackId]) -> Vec<f32> {
+    render(&mut Player::from_song(test_song(playing), 22_050))
+}
+
+#[test]
+fn solo_silences_other_tracks() {
+    let mut player = Player::from_song(test_song(&TrackId::ALL), 22_050);
+    player.track_controls_mut(TrackId::Melody(1)).solo = true;
+    assert!(render(&mut player) == only(&[TrackId::Melody(1)]));
+    // Several tracks can be soloed, and muting still applies to them
+    let mut player = Player::from_song(test_song(&TrackId::ALL), 22_050);
+    for track in [TrackId::Melody(0), TrackId::Melody(2), TrackId::Percussion] {
+        player.track_controls_mut(track).solo = true;
+    }
+    player.track_controls_mut(TrackId::Melody(2)).mute = true;
+    assert!(render(&mut player) == only(&[TrackId::Melody(0), TrackId::Percussion]));
+}
+
+#[test]
+fn mute() {
+    let mut player = Player::from_song(test_song(&TrackId::ALL), 22_050);
+    player.track_controls_mut(TrackId::Percussion).mute = true;
+    let playing = [TrackId::Melody(0), TrackId::Melody(1), TrackId::Melody(2)];
+    assert!(render(&mut player) == only(&playing));
+}
+
+#[test]
+fn gain() {
+    let mut player = Player::from_song(test_song(&[TrackId::Melody(2)]), 22_050);
+    player.track_controls_mut(TrackId::Melody(2)).gain = 0.5;
+    let expected: Vec<_> = only(&[TrackId::Melody(2)])
+        .iter()
+        .map(|s| s * 0.5)
+        .collect();
+    assert!(render(&mut player) == expected);
+}
+
+/// The controls belong to the player, so they never end up in the song
+#[test]
+fn song_is_unchanged() {
+    let song = test_song(&TrackId::ALL);
+    let mut player = Player::from_song(song.clone(), 22_050);
+    for track in TrackId::ALL {
+        let controls = player.track_controls_mut(track);
+        controls.mute = true;
+        controls.gain = 0.0;
+    }
+    render(&mut player);
+    assert_eq!(player.song.to_bytes(), song.to_bytes());
+}
@@ -0,0 +1,180 @@
+//! Checks that `Song::validate` finds each kind of problem, and only where it is
+
+use {
+    piyopiyo::{Event, Location, Player, Problem, Severity, Song, TrackId},
+    std::ops::Range,
+};
+
+/// The problems `song` has, with their locations
+fn problems(song: &Song) -> Vec<(Location, Problem)> {
+    song.validate()
+        .into_iter()
+        .map(|diag| (diag.location, diag.problem))
+        .collect()
+}
+
+#[test]
+fn new_song_is_valid() {
+    assert_eq!(problems(&Song::new(8)), []);
+}
+
+#[test]
+fn no_events() {
+    let song = Song::new(0);
+    let problems = problems(&song);
+    assert!(problems.contains(&(Location::Song, Problem::NoEvents)));
+    assert_eq!(Problem::NoEvents.severity(), Severity::Error);
+}
+
+#[test]
+fn zero_event_wait() {
+    let mut song = Song::new(8);
+    song.event_wait_ms = 0;
+    assert_eq!(problems(&song), [(Location::Song, Problem::ZeroEventWait)]);
+}
+
+#[test]
+fn repeat_range_inverted() {
+    let mut song = Song::new(8);
+    song.repeat_range = Range { start: 5, end: 3 };
+    assert_eq!(
+        problems(&song),
+        [(Location::Song, Problem::RepeatRangeInverted)]
+    );
+}
+
+#[test]
+fn repeat_range_empty() {
+    let mut song = Song::new(8);
+    song.repeat_range = 3..3;
+    assert_eq!(
+        problems(&song),
+        [(Location::Song, Problem::RepeatRangeEmpty)]
+    );
+    assert_eq!(Problem::RepeatRangeEmpty.severity(), Severity::Warning);
+}
+
+#[test]
+fn repeat_range_past_end() {
+    let past_end = (Location::Song, Problem::RepeatRangePastEnd { n_events: 8 });
+    for (start, end) in [(0, 9), (8, 8), (9, 9), (9, 4)] {
+        let mut song = Song::new(8);
+        song.repeat_range = Range { start, end };
+        assert!(
+            problems(&song).contains(&past_end),
+            "{start}..{end} isn't reported"
+        );
+    }
+    assert_eq!(past_end.1.severity(), Severity::Error);
+}
+
+#[test]
+fn event_count_mismatch() {
+    let mut song = Song::new(8);
+    let events = song.melody_tracks[2].base.events[..6].to_vec();
+    song.melody_tracks[2].base.events = events.into_boxed_slice();
+    assert_eq!(
+        problems(&song),
+        [(
+            Location::Track(TrackId::Melody(2)),
+            Problem::EventCountMismatch {
+                expected: 8,
+                actual: 6
+            }
+        )]
+    );
+}
+
+#[test]
+fn volume_out_of_range() {
+    let mut song = Song::new(8);
+    song.percussion_track.base.vol = 301;
+    assert_eq!(
+        problems(&song),
+        [(
+            Location::Track(TrackId::Percussion),
+            Problem::VolumeOutOfRange(301)
+        )]
+    );
+}
+
+#[test]
+fn octave_out_of_range() {
+    let mut song = Song::new(8);
+    song.melody_tracks[0].octave = 8;
+    assert_eq!(
+        problems(&song),
+        [(
+            Location::Track(TrackId::Melody(0)),
+            Problem::OctaveOutOfRange(8)
+        )]
+    );
+}
+
+#[test]
+fn zero_length() {
+    let mut song = Song::new(8);
+    song.melody_tracks[1].len = 0;
+    assert_eq!(
+        problems(&song),
+        [(Location::Track(TrackId::Melody(1)), Problem::ZeroLength)]
+    );
+}
+
+#[test]
+fn silent_waveform() {
+    let mut song = Song::new(8);
+    song.melody_tracks[1].waveform = [0; 256];
+    assert_eq!(
+        problems(&song),
+        [(Location::Track(TrackId::Melody(1)), Problem::SilentWaveform)]
+    );
+}
+
+#[test]
+fn silent_envelope() {
+    let mut song = Song::new(8);
+    song.melody_tracks[2].envelope = [0; 64];
+    assert_eq!(
+        problems(&song),
+        [(Location::Track(TrackId::Melody(2)), Problem::SilentEnvelope)]
+    );
+}
+
+/// Invalid pans can't be loaded, but can be put into events, and are ignored during playback
+#[test]
+fn invalid_pan() {
+    let mut song = Song::new(8);
+    song.melody_tracks[1].base.events[3] = Event::from_bits(0xFF00_0001);
+    assert_eq!(
+        problems(&song),
+        [(
+            Location::Event(TrackId::Melody(1), 3),
+            Problem::InvalidPan(0xFF)
+        )]
+    );
+    assert_eq!(Problem::InvalidPan(0xFF).severity(), Severity::Error);
+    assert!(Song::load(&song.to_bytes()).is_err());
+    let mut player = Player::from_song(song, 11_025);
+    let mut buf = [0; 2048];
+    player.render_next(&mut buf);
+}
+
+/// The loudest instrument is valid, and must play without overflowing
+#[test]
+fn loudest_instrument_plays() {
+    let mut song = Song::new(8);
+    for track in &mut song.melody_tracks {
+        track.waveform = [i8::MIN; 256];
+        track.envelope = [u8::MAX; 64];
+        track.base.vol = 300;
+        for event in &mut track.base.events {
+            event.set_key_down(0);
+        }
+    }
+    assert_eq!(problems(&song), []);
+    let mut player = Player::new(&song.to_bytes(), 11_025).unwrap();
+    let mut buf = [0; 2048];
+    player.render_next(&mut buf);
+    assert!(buf.iter().any(|&s| s != 0));
+}
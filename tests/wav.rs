@@ -0,0 +1,107 @@
+//! Checks rendering songs into WAVE files
+#![cfg(feature = "std")]
+
+use piyopiyo::{
+    Player, Song,
+    export::wav::{self, SampleFormat, WavOptions},
+};
+
+/// Frames per event at 44100 Hz, with the default event wait of 125 ms
+const EVENT_FRAMES: usize = 5513;
+
+/// A song playing a note on every event of the first melody track
+fn test_song() -> Song {
+    let mut song = Song::new(16);
+    for event in &mut song.melody_tracks[0].base.events {
+        event.set_key_down(12);
+    }
+    song
+}
+
+/// The chunks of a RIFF WAVE file, by their ID
+fn chunks(bytes: &[u8]) -> Vec<(&[u8], &[u8])> {
+    assert_eq!(&bytes[..4], b"RIFF");
+    let riff_len = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+    assert_eq!(riff_len, bytes.len() - 8);
+    assert_eq!(&bytes[8..12], b"WAVE");
+    let mut chunks = Vec::new();
+    let mut rest = &bytes[12..];
+    while let Some((header, data)) = rest.split_first_chunk::<8>() {
+        let len = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+        let (data, next) = data.split_at(len);
+        chunks.push((&header[..4], data));
+        rest = next;
+    }
+    chunks
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn i16_header_and_length() {
+    let mut bytes = Vec::new();
+    wav::write(&test_song(), &WavOptions::default(), &mut bytes).unwrap();
+    assert_eq!(bytes.len(), 44 + 16 * EVENT_FRAMES * 4);
+    let chunks = chunks(&bytes);
+    let [(b"fmt ", fmt), (b"data", data)] = chunks[..] else {
+        panic!("unexpected chunks");
+    };
+    assert_eq!(fmt.len(), 16);
+    // PCM, stereo, 44100 Hz, 4 bytes per frame, 16 bits per sample
+    assert_eq!(u16_at(fmt, 0), 1);
+    assert_eq!(u16_at(fmt, 2), 2);
+    assert_eq!(u32_at(fmt, 4), 44_100);
+    assert_eq!(u32_at(fmt, 8), 44_100 * 4);
+    assert_eq!(u16_at(fmt, 12), 4);
+    assert_eq!(u16_at(fmt, 14), 16);
+    // The samples are the ones the player renders
+    let mut player = Player::from_song(test_song(), 44_100);
+    let mut buf = vec![0; 16 * EVENT_FRAMES * 2];
+    player.render_next(&mut buf);
+    let expected: Vec<u8> = buf.iter().flat_map(|s| s.to_le_bytes()).collect();
+    assert!(data == expected);
+}
+
+#[test]
+fn f32_header_and_length() {
+    let opts = WavOptions {
+        sample_rate: 22_050,
+        format: SampleFormat::F32,
+        loops: 2,
+        tail_ms: 500,
+        ..WavOptions::default()
+    };
+    let mut bytes = Vec::new();
+    wav::write(&test_song(), &opts, &mut bytes).unwrap();
+    // Two passes through the song at 2757 frames per event, and half a second of tail
+    let frames = 2 * 16 * 2757 + 11_025;
+    assert_eq!(bytes.len(), 58 + frames * 8);
+    let chunks = chunks(&bytes);
+    let [(b"fmt ", fmt), (b"fact", fact), (b"data", data)] = chunks[..] else {
+        panic!("unexpected chunks");
+    };
+    assert_eq!(fmt.len(), 18);
+    // IEEE float, stereo, 22050 Hz, 8 bytes per frame, 32 bits per sample, no extension
+    assert_eq!(u16_at(fmt, 0), 3);
+    assert_eq!(u16_at(fmt, 2), 2);
+    assert_eq!(u32_at(fmt, 4), 22_050);
+    assert_eq!(u32_at(fmt, 8), 22_050 * 8);
+    assert_eq!(u16_at(fmt, 12), 8);
+    assert_eq!(u16_at(fmt, 14), 32);
+    assert_eq!(u16_at(fmt, 16), 0);
+    assert_eq!(u32_at(fact, 0) as usize, frames);
+    assert_eq!(data.len(), frames * 8);
+    // The song plays throughout, and the tail rings out to silence
+    let samples: Vec<f32> = data
+        .chunks_exact(4)
+        .map(|s| f32::from_le_bytes(s.try_into().unwrap()))
+        .collect();
+    assert!(samples[..frames * 2 - 1000].iter().any(|&s| s != 0.0));
+    assert!(samples[frames * 2 - 100..].iter().all(|&s| s == 0.0));
+}